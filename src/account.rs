@@ -0,0 +1,147 @@
+use crate::{
+    error::{check_response, Error, Result},
+    link::client,
+    retry::RetryPolicy,
+};
+use reqwest::{
+    blocking::{Client, RequestBuilder, Response},
+    StatusCode,
+};
+use serde::{Deserialize, Serialize};
+
+/// The header Seafile marks logins of accounts with two-factor
+/// authentication with, and expects the one-time password in.
+const OTP_HEADER: &str = "X-Seafile-OTP";
+
+#[derive(Serialize)]
+struct AuthTokenForm<'a> {
+    username: &'a str,
+    password: &'a str,
+}
+
+#[derive(Deserialize)]
+struct AuthToken {
+    token: String,
+}
+
+/// The account an API token belongs to.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AccountInfo {
+    pub email: String,
+    #[serde(default)]
+    pub name: String,
+    /// Bytes used, as reported by the server.
+    #[serde(default)]
+    pub usage: i64,
+    /// Bytes available in total; negative if unlimited.
+    #[serde(default)]
+    pub total: i64,
+}
+
+/// Exchanges `username` and `password` for an API token.
+///
+/// For accounts with two-factor authentication, `otp` is asked for the
+/// one-time password. The login is never retried, as Seafile locks accounts
+/// out after too many failed attempts.
+pub fn login(
+    client: &Client,
+    server: &str,
+    username: &str,
+    password: &str,
+    otp: &mut dyn FnMut() -> Result<String>,
+) -> Result<String> {
+    let url = format!("{}/api2/auth-token/", server.trim_end_matches('/'));
+    let form = AuthTokenForm { username, password };
+    let mut resp = client.post(&url).form(&form).send()?;
+    if resp.status() == StatusCode::BAD_REQUEST && resp.headers().contains_key(OTP_HEADER) {
+        resp = client
+            .post(&url)
+            .header(OTP_HEADER, otp()?)
+            .form(&form)
+            .send()?;
+    }
+    if resp.status() == StatusCode::BAD_REQUEST {
+        return Err(Error::WrongPassword);
+    }
+    let auth_token: AuthToken = check_response(resp)?.json()?;
+    Ok(auth_token.token)
+}
+
+/// A client for the web API of a Seafile account, authenticated with an API
+/// token from [`login`].
+pub struct Account {
+    client: Client,
+    retry: RetryPolicy,
+    server: String,
+    token: String,
+}
+
+impl Account {
+    pub fn new(server: &str, token: &str) -> Result<Self> {
+        Ok(Self::with_client(
+            client()?,
+            RetryPolicy::default(),
+            server,
+            token,
+        ))
+    }
+
+    /// Like [`Account::new`], with a client and retry policy configured by
+    /// the caller.
+    pub fn with_client(client: Client, retry: RetryPolicy, server: &str, token: &str) -> Self {
+        Account {
+            client,
+            retry,
+            server: server.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+        }
+    }
+
+    pub fn server(&self) -> &str {
+        &self.server
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    pub(crate) fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub(crate) fn retry(&self) -> &RetryPolicy {
+        &self.retry
+    }
+
+    /// Sends the request `build` makes for API endpoint `path`, e.g.
+    /// `/api2/repos/`, with the token and retries of the account.
+    pub(crate) fn send(
+        &self,
+        path: &str,
+        build: impl Fn(&Client, &str) -> RequestBuilder,
+    ) -> Result<Response> {
+        check_response(self.send_unchecked(path, build)?)
+    }
+
+    /// Like [`Account::send`], leaving error responses to the caller.
+    pub(crate) fn send_unchecked(
+        &self,
+        path: &str,
+        build: impl Fn(&Client, &str) -> RequestBuilder,
+    ) -> Result<Response> {
+        let url = format!("{}{}", self.server, path);
+        self.retry.send(|| {
+            Ok(build(&self.client, &url)
+                .header("Authorization", format!("Token {}", self.token))
+                .header("Accept", "application/json"))
+        })
+    }
+
+    /// Fetches the account the token belongs to, which also checks that the
+    /// token is still valid.
+    pub fn info(&self) -> Result<AccountInfo> {
+        Ok(self
+            .send("/api2/account/info/", |client, url| client.get(url))?
+            .json()?)
+    }
+}
@@ -0,0 +1,222 @@
+use crate::{
+    account::Account,
+    error::{check_response, Error, Result},
+    link::{client, open_page, post_password, LinkKind, LinkPage},
+    repo::normalize_path,
+    retry::RetryPolicy,
+};
+use percent_encoding::percent_decode_str;
+use reqwest::blocking::{Client, Response};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+/// An entry of a shared folder as listed by the share link dirents API.
+#[derive(Debug, Deserialize)]
+pub struct ShareDirent {
+    pub is_dir: bool,
+    #[serde(rename = "file_name", alias = "folder_name")]
+    pub name: String,
+    #[serde(rename = "file_path", alias = "folder_path")]
+    pub path: String,
+}
+
+#[derive(Deserialize)]
+struct ShareDirents {
+    dirent_list: Vec<ShareDirent>,
+}
+
+/// A file saved from a share link.
+#[derive(Debug, Serialize)]
+pub struct DownloadedFile {
+    /// Path of the file in a shared folder; absent for shared files.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    pub local_path: PathBuf,
+    pub size: u64,
+}
+
+/// Rejects remote names that would escape the local target directory.
+fn check_local_name(name: &str) -> Result<&str> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(&['/', '\\'][..]) {
+        return Err(Error::Layout(format!(
+            "refusing to save remote file named {:?}",
+            name
+        )));
+    }
+    Ok(name)
+}
+
+fn save_response(mut resp: Response, path: &Path) -> Result<u64> {
+    let mut file =
+        fs::File::create(path).map_err(|e| Error::Local(format!("{}: {}", path.display(), e)))?;
+    Ok(resp.copy_to(&mut file)?)
+}
+
+/// A client for a download link of a folder, `<server>/d/<token>/`, or of a
+/// single file, `<server>/f/<token>/`.
+pub struct ShareLink {
+    client: Client,
+    retry: RetryPolicy,
+    server: String,
+    kind: LinkKind,
+    token: String,
+    csrfmiddlewaretoken: Option<String>,
+}
+
+impl ShareLink {
+    /// Opens download link `token` of `kind` on `server`.
+    pub fn open(server: &str, kind: LinkKind, token: &str) -> Result<Self> {
+        Self::open_with_client(client()?, RetryPolicy::default(), server, kind, token)
+    }
+
+    /// Like [`ShareLink::open`], with a client and retry policy configured
+    /// by the caller. The client needs a cookie store to keep the session of
+    /// the link.
+    pub fn open_with_client(
+        client: Client,
+        retry: RetryPolicy,
+        server: &str,
+        kind: LinkKind,
+        token: &str,
+    ) -> Result<Self> {
+        if kind == LinkKind::Upload {
+            return Err(Error::Usage("not a download link".into()));
+        }
+        let server = server.trim_end_matches('/').to_string();
+        let csrfmiddlewaretoken = match open_page(&client, &retry, &kind.url(&server, token))? {
+            LinkPage::Open(_) => None,
+            LinkPage::Locked {
+                csrfmiddlewaretoken,
+            } => Some(csrfmiddlewaretoken),
+        };
+        Ok(ShareLink {
+            client,
+            retry,
+            server,
+            kind,
+            token: token.to_string(),
+            csrfmiddlewaretoken,
+        })
+    }
+
+    pub fn server(&self) -> &str {
+        &self.server
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    pub fn kind(&self) -> LinkKind {
+        self.kind
+    }
+
+    pub fn needs_password(&self) -> bool {
+        self.csrfmiddlewaretoken.is_some()
+    }
+
+    /// Unlocks a link protected by a password.
+    pub fn authenticate(&mut self, password: &str) -> Result<()> {
+        if let Some(csrfmiddlewaretoken) = &self.csrfmiddlewaretoken {
+            post_password(
+                &self.client,
+                &self.kind.url(&self.server, &self.token),
+                &self.token,
+                csrfmiddlewaretoken,
+                password,
+            )?;
+            self.csrfmiddlewaretoken = None;
+        }
+        Ok(())
+    }
+
+    /// Lists the entries of directory `path` of a shared folder.
+    pub fn list_dir(&self, path: &str) -> Result<Vec<ShareDirent>> {
+        let url = format!(
+            "{}/api/v2.1/share-links/{}/dirents/",
+            self.server, self.token
+        );
+        let resp = self
+            .retry
+            .send(|| Ok(self.client.get(&url).query(&[("path", path)])))?;
+        let dirents: ShareDirents = check_response(resp)?.json()?;
+        Ok(dirents.dirent_list)
+    }
+
+    /// Saves a shared file to `dest`, or into it if it is a directory.
+    pub fn download_file(&self, dest: &Path) -> Result<DownloadedFile> {
+        let url = LinkKind::File.url(&self.server, &self.token);
+        let resp = self
+            .retry
+            .send(|| Ok(self.client.get(&url).query(&[("dl", "1")])))?;
+        let resp = check_response(resp)?;
+        let local_path = if dest.is_dir() {
+            // The download is redirected to the file server, which names the
+            // file in the last path segment.
+            let name = resp
+                .url()
+                .path_segments()
+                .and_then(|mut segments| segments.next_back())
+                .map(|name| percent_decode_str(name).decode_utf8_lossy().into_owned())
+                .ok_or_else(|| Error::Layout("no file name in download URL".into()))?;
+            dest.join(check_local_name(&name)?)
+        } else {
+            dest.to_path_buf()
+        };
+        let size = save_response(resp, &local_path)?;
+        Ok(DownloadedFile {
+            path: None,
+            local_path,
+            size,
+        })
+    }
+
+    /// Mirrors directory `path` of a shared folder into `dest`, calling
+    /// `on_file` for every file saved.
+    pub fn download_dir(
+        &self,
+        path: &str,
+        dest: &Path,
+        on_file: &mut dyn FnMut(DownloadedFile),
+    ) -> Result<()> {
+        fs::create_dir_all(dest).map_err(|e| Error::Local(format!("{}: {}", dest.display(), e)))?;
+        for dirent in self.list_dir(path)? {
+            let local_path = dest.join(check_local_name(&dirent.name)?);
+            if dirent.is_dir {
+                self.download_dir(&dirent.path, &local_path, on_file)?;
+                continue;
+            }
+            let url = format!("{}/d/{}/files/", self.server, self.token);
+            let resp = self.retry.send(|| {
+                Ok(self
+                    .client
+                    .get(&url)
+                    .query(&[("p", dirent.path.as_str()), ("dl", "1")]))
+            })?;
+            let size = save_response(check_response(resp)?, &local_path)?;
+            on_file(DownloadedFile {
+                path: Some(dirent.path),
+                local_path,
+                size,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Account {
+    /// Opens file `path` of library `repo_id` for reading its content.
+    pub fn read_file(&self, repo_id: &str, path: &str) -> Result<impl Read> {
+        let path = normalize_path(path);
+        let url: String = self
+            .send(&format!("/api2/repos/{}/file/", repo_id), |client, url| {
+                client.get(url).query(&[("p", path.as_str())])
+            })?
+            .json()?;
+        check_response(self.retry().send(|| Ok(self.client().get(&url)))?)
+    }
+}
@@ -0,0 +1,124 @@
+use reqwest::blocking::Response;
+use std::{
+    fmt::{self, Display},
+    io,
+};
+
+/// Everything that can go wrong talking to a Seafile server.
+///
+/// The variants are coarse on purpose: each one calls for a different
+/// reaction from the caller, see [`Error::exit_code`].
+#[derive(Debug)]
+pub enum Error {
+    /// Malformed arguments or configuration.
+    Usage(String),
+    /// Local file system failures.
+    Local(String),
+    /// The server could not be reached or the connection broke.
+    Network(reqwest::Error),
+    WrongPassword,
+    /// The share link does not exist or has expired.
+    InvalidLink(String),
+    /// A page or response did not look the way this crate expects, usually
+    /// because the server runs an unsupported Seafile version.
+    Layout(String),
+    QuotaExceeded,
+    /// The server refused the request.
+    Rejected(String),
+    /// A remote file or directory already exists.
+    Conflict(String),
+}
+
+impl Error {
+    /// The exit code the command line tool reports this error with.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Local(_) => 1,
+            Error::Usage(_) => 2,
+            Error::Network(_) => 3,
+            Error::WrongPassword => 4,
+            Error::InvalidLink(_) => 5,
+            Error::Layout(_) => 6,
+            Error::QuotaExceeded => 7,
+            Error::Rejected(_) => 8,
+            Error::Conflict(_) => 9,
+        }
+    }
+
+    /// A stable name of the variant for machine-readable output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Local(_) => "local",
+            Error::Usage(_) => "usage",
+            Error::Network(_) => "network",
+            Error::WrongPassword => "wrong_password",
+            Error::InvalidLink(_) => "invalid_link",
+            Error::Layout(_) => "layout",
+            Error::QuotaExceeded => "quota_exceeded",
+            Error::Rejected(_) => "rejected",
+            Error::Conflict(_) => "conflict",
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Usage(msg) | Error::Local(msg) => write!(f, "{}", msg),
+            Error::Network(e) => write!(f, "network error: {}", e),
+            Error::WrongPassword => write!(f, "wrong password"),
+            Error::InvalidLink(msg) => write!(f, "invalid or expired link: {}", msg),
+            Error::Layout(msg) => write!(f, "unexpected server response: {}", msg),
+            Error::QuotaExceeded => write!(f, "quota exceeded"),
+            Error::Rejected(msg) => write!(f, "rejected by server: {}", msg),
+            Error::Conflict(msg) => write!(f, "conflict: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_decode() {
+            Error::Layout(e.to_string())
+        } else {
+            Error::Network(e)
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Local(e.to_string())
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Passes successful responses through and turns the others into errors,
+/// keeping the message Seafile put in the body.
+pub(crate) fn check_response(resp: Response) -> Result<Response> {
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(resp);
+    }
+    let body = resp.text().unwrap_or_default();
+    let message = serde_json::from_str::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|value| {
+            ["error_msg", "error", "detail"].iter().find_map(|key| {
+                value
+                    .get(key)
+                    .and_then(|msg| msg.as_str())
+                    .map(String::from)
+            })
+        })
+        .unwrap_or_else(|| body.trim().to_string());
+    Err(match status.as_u16() {
+        // Seafile's file server answers uploads over quota with this status.
+        443 => Error::QuotaExceeded,
+        _ if message.is_empty() => Error::Rejected(status.to_string()),
+        _ => Error::Rejected(format!("{}: {}", status, message)),
+    })
+}
@@ -0,0 +1,39 @@
+//! Clients for the share links of a Seafile server, built on the pages and
+//! web APIs its browser front-end uses.
+//!
+//! ```no_run
+//! use seaf_web::UploadLink;
+//!
+//! let mut link = UploadLink::open("https://cloud.tsinghua.edu.cn", "0123456789abcdef0123")?;
+//! if link.needs_password() {
+//!     link.authenticate("secret")?;
+//! }
+//! let file = link.upload("report.pdf")?;
+//! println!("{} {} {}", file.id, file.name, file.size);
+//! # Ok::<(), seaf_web::Error>(())
+//! ```
+
+pub mod account;
+pub mod download;
+mod error;
+pub mod link;
+pub mod progress;
+pub mod repo;
+mod retry;
+pub mod share;
+pub mod sync;
+pub mod upload;
+#[cfg(target_os = "linux")]
+pub mod watch;
+
+pub use crate::{
+    account::Account,
+    download::ShareLink,
+    error::{Error, Result},
+    link::{Link, LinkKind},
+    progress::Progress,
+    repo::{Dirent, Library, RemotePath},
+    retry::RetryPolicy,
+    share::LinkInfo,
+    upload::{FileUploadResp, UploadLink},
+};
@@ -0,0 +1,236 @@
+use crate::{
+    error::{check_response, Error, Result},
+    retry::RetryPolicy,
+};
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest::{blocking::Client, StatusCode, Url};
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+
+/// The kinds of share links, named after the path they are served under.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LinkKind {
+    /// An upload link, `<server>/u/d/<token>/`.
+    Upload,
+    /// A download link of a folder, `<server>/d/<token>/`.
+    Dir,
+    /// A download link of a single file, `<server>/f/<token>/`.
+    File,
+}
+
+impl LinkKind {
+    pub fn prefix(self) -> &'static str {
+        match self {
+            LinkKind::Upload => "u/d",
+            LinkKind::Dir => "d",
+            LinkKind::File => "f",
+        }
+    }
+
+    /// The URL of the page of link `token` on `server`.
+    pub fn url(self, server: &str, token: &str) -> String {
+        format!("{}/{}/{}/", server, self.prefix(), token)
+    }
+}
+
+pub fn check_token(token: &str) -> Result<()> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"^[0-9a-f]{20}$").unwrap();
+    }
+    if RE.is_match(token) {
+        Ok(())
+    } else {
+        Err(Error::Usage(format!(
+            "invalid token {:?}: expected 20 lowercase hexadecimal characters",
+            token
+        )))
+    }
+}
+
+/// A share link as given by the user.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Link {
+    /// The server the link lives on, if it was given as a full URL.
+    pub server: Option<String>,
+    pub kind: LinkKind,
+    pub token: String,
+}
+
+impl Link {
+    /// Splits a share link into the server it lives on, its kind and its
+    /// token.
+    ///
+    /// `link` is either a full URL such as
+    /// `https://host/u/d/0123456789abcdef0123/`, in which case the server is
+    /// taken from the URL (including any sub-path Seafile is deployed under),
+    /// or a bare token if only one kind is accepted.
+    pub fn parse(link: &str, kinds: &[LinkKind]) -> Result<Self> {
+        let expected = kinds
+            .iter()
+            .map(|kind| format!("<server>/{}/<token>/", kind.prefix()))
+            .collect::<Vec<_>>()
+            .join(" or ");
+        if !link.contains("://") {
+            return match kinds {
+                [kind] => {
+                    check_token(link)?;
+                    Ok(Link {
+                        server: None,
+                        kind: *kind,
+                        token: link.to_string(),
+                    })
+                }
+                _ => Err(Error::Usage(format!(
+                    "invalid link {:?}: expected {}",
+                    link, expected
+                ))),
+            };
+        }
+        let url = Url::parse(link)
+            .map_err(|e| Error::Usage(format!("invalid link {:?}: {}", link, e)))?;
+        let segments: Vec<&str> = url
+            .path_segments()
+            .map(|segments| segments.filter(|s| !s.is_empty()).collect())
+            .unwrap_or_default();
+        for &kind in kinds {
+            let prefix: Vec<&str> = kind.prefix().split('/').collect();
+            if segments.len() < prefix.len() + 1 {
+                continue;
+            }
+            let (base, rest) = segments.split_at(segments.len() - prefix.len() - 1);
+            if rest[..prefix.len()] != prefix[..] {
+                continue;
+            }
+            let token = rest[prefix.len()];
+            check_token(token)?;
+            let mut server = url.origin().ascii_serialization();
+            for segment in base {
+                server.push('/');
+                server.push_str(segment);
+            }
+            return Ok(Link {
+                server: Some(server),
+                kind,
+                token: token.to_string(),
+            });
+        }
+        Err(Error::Usage(format!(
+            "invalid link {:?}: expected {}",
+            link, expected
+        )))
+    }
+}
+
+/// A client suited to share links: it keeps the session cookie of unlocked
+/// links and does not time out on long transfers.
+pub fn client() -> Result<Client> {
+    Ok(Client::builder().timeout(None).cookie_store(true).build()?)
+}
+
+#[derive(Deserialize)]
+struct ServerInfo {
+    version: String,
+}
+
+/// Asks `server` for its Seafile version and returns the major version, or
+/// `None` if the server does not tell, e.g. because a proxy hides the API.
+pub(crate) fn server_major_version(
+    client: &Client,
+    retry: &RetryPolicy,
+    server: &str,
+) -> Result<Option<u32>> {
+    let url = format!("{}/api2/server-info/", server);
+    let resp = retry.send(|| Ok(client.get(&url)))?;
+    if !resp.status().is_success() {
+        return Ok(None);
+    }
+    Ok(resp
+        .json::<ServerInfo>()
+        .ok()
+        .and_then(|info| info.version.split('.').next()?.trim().parse().ok()))
+}
+
+fn get_first_page(client: &Client, retry: &RetryPolicy, url: &str) -> Result<Html> {
+    let resp = retry.send(|| Ok(client.get(url)))?;
+    if resp.status() == StatusCode::NOT_FOUND {
+        return Err(Error::InvalidLink(format!("{} not found", url)));
+    }
+    Ok(Html::parse_document(&check_response(resp)?.text()?))
+}
+
+/// Finds the message of Seahub's error page, e.g. "Link is expired".
+pub(crate) fn find_page_error(document: &Html) -> Option<String> {
+    document
+        .select(&Selector::parse(".error").unwrap())
+        .map(|element| element.text().collect::<String>().trim().to_string())
+        .find(|text| !text.is_empty())
+}
+
+fn find_password_form(document: &Html) -> Option<ElementRef<'_>> {
+    document
+        .select(&Selector::parse("form#share-passwd-form").unwrap())
+        .next()
+}
+
+fn extract_token(document: &Html) -> Result<&str> {
+    let form = find_password_form(document).ok_or_else(|| Error::Layout("no such form".into()))?;
+    let input_selector = Selector::parse("input").unwrap();
+    let mut inputs = form.select(&input_selector);
+    inputs
+        .next()
+        .ok_or_else(|| Error::Layout("no such input".into()))?
+        .value()
+        .attr("value")
+        .ok_or_else(|| Error::Layout("no csrfmiddlewaretoken".into()))
+}
+
+/// The first page of a share link: either the link itself or, for links
+/// protected by a password, the password form.
+pub(crate) enum LinkPage {
+    Open(Html),
+    Locked { csrfmiddlewaretoken: String },
+}
+
+pub(crate) fn open_page(client: &Client, retry: &RetryPolicy, url: &str) -> Result<LinkPage> {
+    let document = get_first_page(client, retry, url)?;
+    if find_password_form(&document).is_none() {
+        return Ok(LinkPage::Open(document));
+    }
+    Ok(LinkPage::Locked {
+        csrfmiddlewaretoken: extract_token(&document)?.to_string(),
+    })
+}
+
+#[derive(Serialize)]
+struct SharePasswdForm<'a> {
+    csrfmiddlewaretoken: &'a str,
+    token: &'a str,
+    password: &'a str,
+}
+
+/// Posts the password form of a locked link and returns the unlocked page.
+///
+/// This is never retried: Seafile locks the link out after too many failed
+/// attempts.
+pub(crate) fn post_password(
+    client: &Client,
+    url: &str,
+    token: &str,
+    csrfmiddlewaretoken: &str,
+    password: &str,
+) -> Result<Html> {
+    let resp = client
+        .post(url)
+        .form(&SharePasswdForm {
+            csrfmiddlewaretoken,
+            token,
+            password,
+        })
+        .send()?;
+    let document = Html::parse_document(&check_response(resp)?.text()?);
+    if find_password_form(&document).is_some() {
+        return Err(Error::WrongPassword);
+    }
+    Ok(document)
+}
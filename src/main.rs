@@ -2,161 +2,1900 @@ use clap::{
     crate_authors, crate_description, crate_name, crate_version, App, AppSettings, Arg, ArgMatches,
     SubCommand,
 };
-use lazy_static::lazy_static;
-use regex::Regex;
-use reqwest::blocking::Client;
-use scraper::{Html, Selector};
+use indicatif::{ProgressBar, ProgressStyle};
+#[cfg(target_os = "linux")]
+use seaf_web::watch::Watch;
+use seaf_web::{
+    account::{self, AccountInfo},
+    download::DownloadedFile,
+    link::{self, check_token},
+    repo::find_library,
+    share::{LinkInfo, LinkOptions, LinkPermissions},
+    sync::{Compare, SyncAction, SyncOptions, SyncState},
+    upload::{collect_upload_items, relative_dir, OnConflict, UploadItem, UploadOptions},
+    Account, Dirent, Error, FileUploadResp, Library, Link, LinkKind, Progress, RemotePath, Result,
+    RetryPolicy, ShareLink, UploadLink,
+};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::{
-    path::Path,
-    time::{SystemTime, UNIX_EPOCH},
+    collections::{BTreeMap, BTreeSet},
+    env,
+    fmt::Display,
+    fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
-const BASE_URL: &str = "https://cloud.tsinghua.edu.cn";
+const DEFAULT_SERVER: &str = "https://cloud.tsinghua.edu.cn";
 
-fn get_first_page(client: &Client, token: &str) -> reqwest::Result<Html> {
-    Ok(Html::parse_document(
-        &client
-            .get(&format!("{}/u/d/{}/", BASE_URL, token))
-            .send()?
-            .text()?,
-    ))
+const EXIT_CODES: &str = "EXIT CODES:
+    1    local file system error
+    2    invalid arguments or configuration
+    3    network failure
+    4    wrong password
+    5    invalid or expired link
+    6    unexpected page layout or response (unsupported server version)
+    7    quota exceeded
+    8    request rejected by the server
+    9    remote file already exists (--on-conflict fail, mkdir)";
+
+#[derive(Deserialize, Default)]
+struct Config {
+    server: Option<String>,
+}
+
+fn config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(crate_name!()).join("config.toml"))
+}
+
+fn load_config(path: Option<&Path>) -> Result<Config> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => match config_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(Config::default()),
+        },
+    };
+    let content = fs::read_to_string(&path)
+        .map_err(|e| Error::Usage(format!("{}: {}", path.display(), e)))?;
+    toml::from_str(&content).map_err(|e| Error::Usage(format!("{}: {}", path.display(), e)))
+}
+
+fn resolve_server(matches: &ArgMatches) -> Result<String> {
+    let server = match matches.value_of("server") {
+        Some(server) => server.to_string(),
+        None => load_config(matches.value_of("config").map(Path::new))?
+            .server
+            .unwrap_or_else(|| DEFAULT_SERVER.to_string()),
+    };
+    Ok(server.trim_end_matches('/').to_string())
+}
+
+/// The API tokens `login` saved, by server.
+#[derive(Deserialize, Serialize, Default)]
+struct Credentials {
+    #[serde(default)]
+    servers: BTreeMap<String, ServerCredentials>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct ServerCredentials {
+    username: String,
+    token: String,
+}
+
+fn credentials_path() -> Result<PathBuf> {
+    dirs::config_dir()
+        .map(|dir| dir.join(crate_name!()).join("credentials.toml"))
+        .ok_or_else(|| Error::Local("no configuration directory to keep credentials in".into()))
+}
+
+fn load_credentials(path: &Path) -> Result<Credentials> {
+    if !path.exists() {
+        return Ok(Credentials::default());
+    }
+    let content =
+        fs::read_to_string(path).map_err(|e| Error::Local(format!("{}: {}", path.display(), e)))?;
+    toml::from_str(&content).map_err(|e| Error::Usage(format!("{}: {}", path.display(), e)))
+}
+
+/// Writes the credentials file, readable by the user only since it holds
+/// API tokens.
+fn save_credentials(path: &Path, credentials: &Credentials) -> Result<()> {
+    let local_error = |e: io::Error| Error::Local(format!("{}: {}", path.display(), e));
+    let content = toml::to_string(credentials)
+        .map_err(|e| Error::Local(format!("{}: {}", path.display(), e)))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(local_error)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path).map_err(local_error)?;
+    // The mode above only applies to new files.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))
+            .map_err(local_error)?;
+    }
+    file.write_all(content.as_bytes()).map_err(local_error)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Text,
+    Json,
+    Tsv,
+}
+
+impl OutputFormat {
+    fn from_matches(matches: &ArgMatches) -> Self {
+        match matches.value_of("output") {
+            Some("json") => OutputFormat::Json,
+            Some("tsv") => OutputFormat::Tsv,
+            _ => OutputFormat::Text,
+        }
+    }
+
+    /// Prints one result to stdout: `record` as a JSON line, or `fields`
+    /// separated by spaces or tabs.
+    fn print<T: Serialize>(self, record: &T, fields: &[&dyn Display]) {
+        let fields = fields.iter().map(|field| field.to_string());
+        match self {
+            OutputFormat::Json => println!("{}", serde_json::to_string(record).unwrap()),
+            OutputFormat::Text => println!("{}", fields.collect::<Vec<_>>().join(" ")),
+            OutputFormat::Tsv => println!(
+                "{}",
+                fields
+                    .map(|field| escape_tsv(&field))
+                    .collect::<Vec<_>>()
+                    .join("\t")
+            ),
+        }
+    }
+
+    fn print_error(self, error: &Error) {
+        match self {
+            OutputFormat::Json => eprintln!(
+                "{}",
+                json!({
+                    "error": error.to_string(),
+                    "kind": error.kind(),
+                    "exit_code": error.exit_code(),
+                })
+            ),
+            _ => eprintln!("error: {}", error),
+        }
+    }
+}
+
+fn escape_tsv(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn trim_newline(line: &str) -> &str {
+    line.strip_suffix('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .unwrap_or(line)
+}
+
+/// Reads the share link password from, in order, `--password-file`,
+/// `--password-stdin`, `SEAF_WEB_PASSWORD` or an interactive prompt.
+fn read_password(matches: &ArgMatches) -> Result<String> {
+    if let Some(path) = matches.value_of("password-file") {
+        let content =
+            fs::read_to_string(path).map_err(|e| Error::Usage(format!("{}: {}", path, e)))?;
+        return Ok(content.lines().next().unwrap_or_default().to_string());
+    }
+    if matches.is_present("password-stdin") {
+        let mut line = String::new();
+        io::stdin().lock().read_line(&mut line)?;
+        return Ok(trim_newline(&line).to_string());
+    }
+    if let Ok(password) = env::var("SEAF_WEB_PASSWORD") {
+        return Ok(password);
+    }
+    Ok(rpassword::prompt_password_stderr("Password: ")?)
+}
+
+struct ProgressState {
+    position: u64,
+    resumed: u64,
+    started: Instant,
+    reported: Option<(Instant, u64)>,
+}
+
+/// Reports the progress of a transfer, as a bar on a terminal and as
+/// `progress <sent> <total> <name>` lines (or JSON objects) on stderr otherwise.
+struct ProgressReport {
+    name: String,
+    /// Unknown for streams.
+    total: Option<u64>,
+    format: OutputFormat,
+    bar: Option<ProgressBar>,
+    /// Reports of a file uploaded among others only count toward `parent`.
+    quiet: bool,
+    parent: Option<Arc<ProgressReport>>,
+    state: Mutex<ProgressState>,
+}
+
+impl ProgressReport {
+    fn new(name: &str, total: Option<u64>, format: OutputFormat) -> Self {
+        let bar = if atty::is(atty::Stream::Stderr) {
+            let bar = match total {
+                Some(total) => {
+                    let bar = ProgressBar::new(total);
+                    bar.set_style(
+                        ProgressStyle::default_bar()
+                            .template(
+                                "{msg} [{bar:30}] {bytes}/{total_bytes} {bytes_per_sec} ETA {eta}",
+                            )
+                            .progress_chars("=> "),
+                    );
+                    bar
+                }
+                None => {
+                    let bar = ProgressBar::new_spinner();
+                    bar.set_style(
+                        ProgressStyle::default_spinner()
+                            .template("{msg} {spinner} {bytes} {bytes_per_sec}"),
+                    );
+                    bar
+                }
+            };
+            bar.set_message(name);
+            Some(bar)
+        } else {
+            None
+        };
+        ProgressReport {
+            name: name.to_string(),
+            total,
+            format,
+            bar,
+            quiet: false,
+            parent: None,
+            state: Mutex::new(ProgressState {
+                position: 0,
+                resumed: 0,
+                started: Instant::now(),
+                reported: None,
+            }),
+        }
+    }
+
+    /// A report of one of the transfers `parent` combines, which only shows
+    /// up in the progress of `parent`.
+    fn part_of(parent: &Arc<ProgressReport>, total: u64) -> Self {
+        ProgressReport {
+            name: String::new(),
+            total: Some(total),
+            format: parent.format,
+            bar: None,
+            quiet: true,
+            parent: Some(parent.clone()),
+            state: Mutex::new(ProgressState {
+                position: 0,
+                resumed: 0,
+                started: Instant::now(),
+                reported: None,
+            }),
+        }
+    }
+
+    /// A report that shows nothing, for steps that transfer nothing.
+    fn silent(format: OutputFormat) -> Self {
+        ProgressReport {
+            name: String::new(),
+            total: None,
+            format,
+            bar: None,
+            quiet: true,
+            parent: None,
+            state: Mutex::new(ProgressState {
+                position: 0,
+                resumed: 0,
+                started: Instant::now(),
+                reported: None,
+            }),
+        }
+    }
+
+    /// Moves the position by `delta`, which is negative when a failed
+    /// attempt is retried.
+    fn shift(&self, delta: i64) {
+        let mut state = self.state.lock().unwrap();
+        state.position = (state.position as i64 + delta).max(0) as u64;
+        self.report(&mut state, false);
+    }
+
+    fn report(&self, state: &mut ProgressState, force: bool) {
+        if self.quiet {
+            return;
+        }
+        if let Some(bar) = &self.bar {
+            bar.set_position(state.position);
+            return;
+        }
+        let now = Instant::now();
+        match state.reported {
+            Some((_, position)) if position == state.position => {}
+            Some((reported, _)) if !force && now - reported < Duration::from_secs(1) => {}
+            _ => {
+                match self.format {
+                    OutputFormat::Json => eprintln!(
+                        "{}",
+                        json!({ "progress": {
+                            "name": self.name,
+                            "sent": state.position,
+                            "total": self.total,
+                        } })
+                    ),
+                    OutputFormat::Tsv => eprintln!(
+                        "progress\t{}\t{}\t{}",
+                        state.position,
+                        self.total.map_or("-".into(), |total| total.to_string()),
+                        escape_tsv(&self.name)
+                    ),
+                    OutputFormat::Text => eprintln!(
+                        "progress {} {} {}",
+                        state.position,
+                        self.total.map_or("-".into(), |total| total.to_string()),
+                        self.name
+                    ),
+                }
+                state.reported = Some((now, state.position));
+            }
+        }
+    }
+
+    /// Finishes the report and returns the bytes sent and the time taken.
+    fn finish(&self) -> (u64, Duration) {
+        let mut state = self.state.lock().unwrap();
+        match &self.bar {
+            Some(bar) => bar.finish_and_clear(),
+            None => self.report(&mut state, true),
+        }
+        (state.position - state.resumed, state.started.elapsed())
+    }
+}
+
+impl Progress for ProgressReport {
+    fn resume_at(&self, position: u64) {
+        self.rewind(position);
+        self.state.lock().unwrap().resumed = position;
+    }
+
+    fn rewind(&self, position: u64) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(parent) = &self.parent {
+            parent.shift(position as i64 - state.position as i64);
+        }
+        state.position = position;
+        self.report(&mut state, false);
+    }
+
+    fn inc(&self, delta: u64) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(parent) = &self.parent {
+            parent.shift(delta as i64);
+        }
+        state.position += delta;
+        self.report(&mut state, false);
+    }
 }
 
-fn extract_token(document: &Html) -> Result<&str, Box<dyn std::error::Error>> {
-    let form = document
-        .select(&Selector::parse("form#share-passwd-form").unwrap())
-        .next()
-        .ok_or("no such form")?;
-    let input_selector = Selector::parse("input").unwrap();
-    let mut inputs = form.select(&input_selector);
-    Ok(inputs
-        .next()
-        .ok_or("no such input")?
-        .value()
-        .attr("value")
-        .ok_or("no csrfmiddlewaretoken")?)
+/// Formats a byte count with a binary unit, e.g. `1.5MiB`.
+fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{:.0}{}", value, UNITS[unit])
+    } else {
+        format!("{:.2}{}", value, UNITS[unit])
+    }
 }
 
+/// The result of one uploaded file, as printed by `--output json`.
 #[derive(Serialize)]
-struct SharePasswdForm<'a> {
-    csrfmiddlewaretoken: &'a str,
+struct UploadRecord<'a> {
+    server: &'a str,
     token: &'a str,
-    password: &'a str,
-}
-
-fn post_password(
-    client: &Client,
-    token: &str,
-    csrfmiddlewaretoken: &str,
-    password: &str,
-) -> Result<Html, Box<dyn std::error::Error>> {
-    Ok(Html::parse_document(
-        &client
-            .post(&format!("{}/u/d/{}/", BASE_URL, token))
-            .form(&SharePasswdForm {
-                csrfmiddlewaretoken,
-                token,
-                password,
-            })
-            .send()?
-            .text()?,
+    /// Path of the file relative to the root of the upload link.
+    path: String,
+    #[serde(flatten)]
+    file: &'a FileUploadResp,
+    /// Name of the local file if the server stored it under another name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    local_name: Option<&'a str>,
+    /// Seconds the upload took.
+    elapsed: f64,
+    /// Average speed in bytes per second.
+    speed: f64,
+}
+
+/// The result of one downloaded file, as printed by `--output json`.
+#[derive(Serialize)]
+struct DownloadRecord<'a> {
+    server: &'a str,
+    token: &'a str,
+    #[serde(flatten)]
+    file: &'a DownloadedFile,
+}
+
+fn retry_policy(matches: &ArgMatches) -> Result<RetryPolicy> {
+    match matches.value_of("retries").unwrap().parse() {
+        Ok(max_retries) => Ok(RetryPolicy {
+            max_retries,
+            ..RetryPolicy::default()
+        }),
+        Err(_) => Err(Error::Usage("retries must be a non-negative number".into())),
+    }
+}
+
+/// The `--chunk-size` in bytes.
+fn chunk_size(matches: &ArgMatches) -> Result<u64> {
+    match matches.value_of("chunk-size").unwrap().parse::<u64>() {
+        Ok(mib) if mib > 0 => mib
+            .checked_mul(1 << 20)
+            .ok_or_else(|| Error::Usage("chunk size is too large".into())),
+        _ => Err(Error::Usage(
+            "chunk size must be a positive number of MiB".into(),
+        )),
+    }
+}
+
+/// The server of a link given as a full URL, or the configured one.
+fn link_server(link: &Link, matches: &ArgMatches) -> Result<String> {
+    match &link.server {
+        Some(server) => Ok(server.clone()),
+        None => resolve_server(matches),
+    }
+}
+
+fn handle_upload(matches: &ArgMatches) -> Result<()> {
+    let format = OutputFormat::from_matches(matches);
+    let link = Link::parse(matches.value_of("LINK").unwrap(), &[LinkKind::Upload])?;
+    let dest = relative_dir(matches.value_of("dest").unwrap_or("/"))?;
+    let file_paths: Vec<&str> = matches
+        .values_of("FILEPATH")
+        .map_or_else(Vec::new, |values| values.collect());
+    let stdin_name = match (&file_paths[..], matches.value_of("name")) {
+        (["-"], Some(name)) => Some(name),
+        (["-"], None) => {
+            return Err(Error::Usage(
+                "uploading from stdin needs --name for the file".into(),
+            ))
+        }
+        (paths, _) if paths.contains(&"-") => {
+            return Err(Error::Usage("- cannot be combined with other paths".into()))
+        }
+        _ => None,
+    };
+    if stdin_name.is_some() && matches.is_present("password-stdin") {
+        return Err(Error::Usage(
+            "--password-stdin cannot be used when uploading from stdin".into(),
+        ));
+    }
+    let mut items = Vec::new();
+    for file_path in file_paths.iter().filter(|_| stdin_name.is_none()) {
+        let path = Path::new(file_path);
+        if !path.exists() {
+            return Err(Error::Usage(format!(
+                "{}: no such file or directory",
+                file_path
+            )));
+        }
+        collect_upload_items(path, &dest, &mut items)?;
+    }
+    let options = UploadOptions {
+        chunk_size: chunk_size(matches)?,
+        on_conflict: match matches.value_of("on-conflict").unwrap() {
+            "overwrite" => OnConflict::Overwrite,
+            "fail" => OnConflict::Fail,
+            _ => OnConflict::Rename,
+        },
+    };
+    let jobs = match matches.value_of("jobs").unwrap().parse::<usize>() {
+        Ok(jobs) if jobs > 0 => jobs,
+        _ => return Err(Error::Usage("jobs must be a positive number".into())),
+    };
+    #[cfg(not(target_os = "linux"))]
+    if matches.is_present("watch") {
+        return Err(Error::Usage("--watch is only supported on Linux".into()));
+    }
+    #[cfg(target_os = "linux")]
+    let watch = match matches.value_of("watch") {
+        Some(dir) if !Path::new(dir).is_dir() => {
+            return Err(Error::Usage(format!("{}: not a directory", dir)))
+        }
+        Some(dir) => match matches.value_of("debounce").unwrap_or("2").parse::<f64>() {
+            Ok(secs) if secs >= 0.0 && secs.is_finite() => {
+                Some((Path::new(dir), Duration::from_secs_f64(secs)))
+            }
+            _ => {
+                return Err(Error::Usage(
+                    "debounce must be a non-negative number of seconds".into(),
+                ))
+            }
+        },
+        None => None,
+    };
+    let mut upload_link = UploadLink::open_with_client(
+        link::client()?,
+        retry_policy(matches)?,
+        &link_server(&link, matches)?,
+        &link.token,
+    )?;
+    if upload_link.needs_password() {
+        upload_link.authenticate(&read_password(matches)?)?;
+    }
+    #[cfg(target_os = "linux")]
+    if let Some((dir, debounce)) = watch {
+        let target = format!(
+            "{}\n{}\n{}",
+            upload_link.server(),
+            upload_link.token(),
+            dest
+        );
+        let state_path = state_path("watch", &target, dir)?;
+        let watch = Watch {
+            dir,
+            dest: &dest,
+            debounce,
+            jobs,
+            state_path: &state_path,
+        };
+        return upload_watch(&upload_link, &watch, &options, format);
+    }
+    if let Some(name) = stdin_name {
+        let item = UploadItem {
+            path: PathBuf::from("-"),
+            relative_path: dest,
+        };
+        let report = Arc::new(ProgressReport::new(name, None, format));
+        let resp = upload_link.upload_stream(
+            io::stdin(),
+            name,
+            &item.relative_path,
+            &options,
+            &(report.clone() as Arc<dyn Progress>),
+        )?;
+        let (sent, elapsed) = report.finish();
+        let uploaded = Uploaded {
+            resp,
+            sent,
+            elapsed,
+        };
+        print_upload(&upload_link, &item, name, &uploaded, format);
+    } else if jobs == 1 {
+        for item in &items {
+            let length = fs::metadata(&item.path)?.len();
+            let report = Arc::new(ProgressReport::new(
+                &item.path.display().to_string(),
+                Some(length),
+                format,
+            ));
+            let uploaded = upload_one(&upload_link, item, &options, report)?;
+            print_upload(&upload_link, item, &local_name(item), &uploaded, format);
+        }
+    } else {
+        upload_parallel(&upload_link, &items, &options, jobs, format)?;
+    }
+    Ok(())
+}
+
+/// An uploaded file and the statistics of its transfer.
+struct Uploaded {
+    resp: FileUploadResp,
+    sent: u64,
+    elapsed: Duration,
+}
+
+fn upload_one(
+    upload_link: &UploadLink,
+    item: &UploadItem,
+    options: &UploadOptions,
+    report: Arc<ProgressReport>,
+) -> Result<Uploaded> {
+    let resp = upload_link.upload_item(item, options, &(report.clone() as Arc<dyn Progress>))?;
+    let (sent, elapsed) = report.finish();
+    Ok(Uploaded {
+        resp,
+        sent,
+        elapsed,
+    })
+}
+
+/// Uploads `items` with `jobs` workers sharing the session of the link. The
+/// results are printed in the order of `items`, and the progress is reported
+/// for all of them together.
+fn upload_parallel(
+    upload_link: &UploadLink,
+    items: &[UploadItem],
+    options: &UploadOptions,
+    jobs: usize,
+    format: OutputFormat,
+) -> Result<()> {
+    let lengths = items
+        .iter()
+        .map(|item| fs::metadata(&item.path).map(|metadata| metadata.len()))
+        .collect::<io::Result<Vec<_>>>()?;
+    let combined = Arc::new(ProgressReport::new(
+        &format!("{} files", items.len()),
+        Some(lengths.iter().sum()),
+        format,
+    ));
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+    let result = thread::scope(|scope| {
+        for _ in 0..jobs.min(items.len()) {
+            let sender = sender.clone();
+            let (combined, next, failed, lengths) = (&combined, &next, &failed, &lengths);
+            scope.spawn(move || {
+                while !failed.load(Ordering::SeqCst) {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    if index >= items.len() {
+                        break;
+                    }
+                    let report = Arc::new(ProgressReport::part_of(combined, lengths[index]));
+                    let result = upload_one(upload_link, &items[index], options, report);
+                    if result.is_err() {
+                        failed.store(true, Ordering::SeqCst);
+                    }
+                    if sender.send((index, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+        // Holds the results that finished ahead of an earlier item.
+        let mut pending = BTreeMap::new();
+        let mut next_printed = 0;
+        for (index, result) in receiver {
+            pending.insert(index, result);
+            while let Some(result) = pending.remove(&next_printed) {
+                let item = &items[next_printed];
+                print_upload(upload_link, item, &local_name(item), &result?, format);
+                next_printed += 1;
+            }
+        }
+        Ok(())
+    });
+    combined.finish();
+    result
+}
+
+fn local_name(item: &UploadItem) -> String {
+    item.path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Prints the files of `watch` as they are uploaded, and why uploads
+/// failed.
+#[cfg(target_os = "linux")]
+fn upload_watch(
+    upload_link: &UploadLink,
+    watch: &Watch,
+    options: &UploadOptions,
+    format: OutputFormat,
+) -> Result<()> {
+    watch.run(
+        upload_link,
+        options,
+        |key, length| Arc::new(ProgressReport::new(key, Some(length), format)),
+        |result| match result {
+            Ok(upload) => {
+                let (sent, elapsed) = upload.progress.finish();
+                let item = upload.item;
+                let uploaded = Uploaded {
+                    resp: upload.resp,
+                    sent,
+                    elapsed,
+                };
+                print_upload(upload_link, &item, &local_name(&item), &uploaded, format);
+            }
+            Err(e) => format.print_error(&e),
+        },
+    )
+}
+
+/// Prints the result of uploading local file `local_name`, warning if the
+/// server stored it under another name.
+fn print_upload(
+    upload_link: &UploadLink,
+    item: &UploadItem,
+    local_name: &str,
+    uploaded: &Uploaded,
+    format: OutputFormat,
+) {
+    let resp = &uploaded.resp;
+    let elapsed = uploaded.elapsed.as_secs_f64();
+    let speed = uploaded.sent as f64 / elapsed.max(1e-3);
+    let local_name = Some(local_name).filter(|name| *name != resp.name);
+    if let (Some(local_name), false) = (&local_name, format == OutputFormat::Json) {
+        eprintln!(
+            "{} already exists, stored as {}",
+            item.remote_path(local_name),
+            item.remote_path(&resp.name)
+        );
+    }
+    let record = UploadRecord {
+        server: upload_link.server(),
+        token: upload_link.token(),
+        path: item.remote_path(&resp.name),
+        file: resp,
+        local_name,
+        elapsed,
+        speed,
+    };
+    match format {
+        OutputFormat::Text => format.print(
+            &record,
+            &[
+                &resp.id,
+                &resp.name,
+                &resp.size,
+                &format!("{:.2}s", elapsed),
+                &format!("{}/s", format_bytes(speed)),
+            ],
+        ),
+        _ => format.print(
+            &record,
+            &[
+                &resp.id,
+                &resp.name,
+                &resp.size,
+                &format!("{:.3}", elapsed),
+                &(speed as u64),
+            ],
+        ),
+    }
+}
+
+/// The account logged in to, as printed by `--output json`.
+#[derive(Serialize)]
+struct LoginRecord<'a> {
+    server: &'a str,
+    username: &'a str,
+    #[serde(flatten)]
+    info: &'a AccountInfo,
+}
+
+/// Reads a line from stdin after prompting for it on stderr.
+fn prompt_line(prompt: &str) -> Result<String> {
+    eprint!("{}", prompt);
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(trim_newline(&line).to_string())
+}
+
+fn handle_login(matches: &ArgMatches) -> Result<()> {
+    let format = OutputFormat::from_matches(matches);
+    let server = resolve_server(matches)?;
+    let retry = retry_policy(matches)?;
+    let path = credentials_path()?;
+    let mut credentials = load_credentials(&path)?;
+    if !matches.is_present("force") {
+        let saved = credentials.servers.get(&server).filter(|saved| {
+            matches
+                .value_of("username")
+                .is_none_or(|username| username == saved.username)
+        });
+        if let Some(saved) = saved {
+            let account =
+                Account::with_client(link::client()?, retry.clone(), &server, &saved.token);
+            // Reuse a saved token of the same user as long as the server
+            // still accepts it.
+            if let Ok(info) = account.info() {
+                let record = LoginRecord {
+                    server: &server,
+                    username: &saved.username,
+                    info: &info,
+                };
+                format.print(&record, &[&server, &saved.username]);
+                return Ok(());
+            }
+        }
+    }
+    let username = match matches.value_of("username") {
+        Some(username) => username.to_string(),
+        None if matches.is_present("password-stdin") => {
+            return Err(Error::Usage("--password-stdin needs --username".into()))
+        }
+        None => prompt_line("Username: ")?,
+    };
+    let password = read_password(matches)?;
+    let client = link::client()?;
+    let token = account::login(
+        &client,
+        &server,
+        &username,
+        &password,
+        &mut || match matches.value_of("otp") {
+            Some(otp) => Ok(otp.to_string()),
+            None => prompt_line("Two-factor authentication code: "),
+        },
+    )?;
+    let info = Account::with_client(client, retry, &server, &token).info()?;
+    credentials.servers.insert(
+        server.clone(),
+        ServerCredentials {
+            username: username.clone(),
+            token,
+        },
+    );
+    save_credentials(&path, &credentials)?;
+    let record = LoginRecord {
+        server: &server,
+        username: &username,
+        info: &info,
+    };
+    format.print(&record, &[&server, &username]);
+    Ok(())
+}
+
+/// The account of the server, authenticated with the token `login` saved.
+fn open_account(matches: &ArgMatches) -> Result<Account> {
+    let server = resolve_server(matches)?;
+    let credentials = load_credentials(&credentials_path()?)?;
+    let saved = credentials.servers.get(&server).ok_or_else(|| {
+        Error::Usage(format!(
+            "not logged in to {}, run `{} login` first",
+            server,
+            crate_name!()
+        ))
+    })?;
+    Ok(Account::with_client(
+        link::client()?,
+        retry_policy(matches)?,
+        &server,
+        &saved.token,
     ))
 }
 
-fn extract_repo_id(document: &Html) -> Result<&str, Box<dyn std::error::Error>> {
-    lazy_static! {
-        static ref RE: Regex = Regex::new(concat!(
-            r"'/ajax/u/d/[0-9a-f]{20}/upload/\?r=",
-            r"([0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12})'"
+/// Formats seconds since the epoch as a UTC date and time.
+fn format_time(secs: i64) -> String {
+    // Howard Hinnant's civil_from_days.
+    let (days, secs) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60
+    )
+}
+
+fn handle_ls(matches: &ArgMatches) -> Result<()> {
+    let format = OutputFormat::from_matches(matches);
+    let account = open_account(matches)?;
+    let spec = match matches.value_of("REMOTE") {
+        Some(spec) => RemotePath::parse(spec)?,
+        None => {
+            for lib in account.list_libraries()? {
+                format.print(
+                    &lib,
+                    &[
+                        &lib.id,
+                        &lib.permission,
+                        &lib.size,
+                        &time_field(format, lib.mtime),
+                        &lib.name,
+                    ],
+                );
+            }
+            return Ok(());
+        }
+    };
+    let lib = account.find_library(&spec.library)?;
+    for dirent in account.list_dir(&lib.id, &spec.path)? {
+        let name = if dirent.is_dir() && format == OutputFormat::Text {
+            format!("{}/", dirent.name)
+        } else {
+            dirent.name.clone()
+        };
+        format.print(
+            &dirent,
+            &[
+                &dirent.kind,
+                &dirent.permission,
+                &dirent.size,
+                &time_field(format, dirent.mtime),
+                &name,
+            ],
+        );
+    }
+    Ok(())
+}
+
+/// A modification time as a text field: readable for people, raw for tools.
+fn time_field(format: OutputFormat, mtime: i64) -> String {
+    match format {
+        OutputFormat::Text => format_time(mtime),
+        _ => mtime.to_string(),
+    }
+}
+
+/// A share link of the account, as printed by `--output json`.
+#[derive(Serialize)]
+struct LinkRecord<'a> {
+    /// `upload` or `download`.
+    kind: &'a str,
+    #[serde(flatten)]
+    info: &'a LinkInfo,
+    password_protected: bool,
+}
+
+impl<'a> LinkRecord<'a> {
+    fn new(info: &'a LinkInfo) -> Self {
+        LinkRecord {
+            kind: if info.is_upload() {
+                "upload"
+            } else {
+                "download"
+            },
+            info,
+            password_protected: info.is_protected(),
+        }
+    }
+}
+
+/// Prints a link as `kind token views expiry password library:path`.
+fn print_link(format: OutputFormat, info: &LinkInfo) {
+    let record = LinkRecord::new(info);
+    let expiry = match &info.expire_date {
+        _ if info.is_expired => "expired",
+        Some(date) => date,
+        None => "never",
+    };
+    format.print(
+        &record,
+        &[
+            &record.kind,
+            &info.token,
+            &info.view_cnt,
+            &expiry,
+            &if record.password_protected {
+                "password"
+            } else {
+                "-"
+            },
+            &format!("{}:{}", info.repo_name, info.path),
+        ],
+    );
+}
+
+/// The token of a share link given as a token or URL.
+fn link_token(link: &str) -> Result<String> {
+    if link.contains("://") {
+        Ok(Link::parse(link, &[LinkKind::Upload, LinkKind::Dir, LinkKind::File])?.token)
+    } else {
+        check_token(link)?;
+        Ok(link.to_string())
+    }
+}
+
+fn handle_share(matches: &ArgMatches) -> Result<()> {
+    let (name, matches) = matches.subcommand();
+    let matches = matches.unwrap();
+    match name {
+        "create-upload-link" => handle_share_create(matches, "upload"),
+        "create-download-link" => handle_share_create(matches, "download"),
+        "list" => handle_share_list(matches),
+        "info" => handle_share_info(matches),
+        "delete" => handle_share_delete(matches),
+        _ => unreachable!(),
+    }
+}
+
+fn handle_share_list(matches: &ArgMatches) -> Result<()> {
+    let format = OutputFormat::from_matches(matches);
+    let account = open_account(matches)?;
+    let filter = match matches.value_of("REMOTE") {
+        Some(spec) => {
+            let remote = RemotePath::parse(spec)?;
+            Some((account.find_library(&remote.library)?.id, remote.path))
+        }
+        None => None,
+    };
+    for info in account.list_links()? {
+        if let Some((repo_id, path)) = &filter {
+            if info.repo_id != *repo_id || !is_within(&info.path, path) {
+                continue;
+            }
+        }
+        print_link(format, &info);
+    }
+    Ok(())
+}
+
+/// Whether `path` is `dir` or lies below it.
+fn is_within(path: &str, dir: &str) -> bool {
+    let path = path.trim_end_matches('/');
+    let dir = dir.trim_end_matches('/');
+    path == dir || path.starts_with(&format!("{}/", dir))
+}
+
+fn handle_share_info(matches: &ArgMatches) -> Result<()> {
+    let format = OutputFormat::from_matches(matches);
+    let account = open_account(matches)?;
+    let info = account.link_info(&link_token(matches.value_of("LINK").unwrap())?)?;
+    print_link(format, &info);
+    Ok(())
+}
+
+fn handle_share_delete(matches: &ArgMatches) -> Result<()> {
+    let format = OutputFormat::from_matches(matches);
+    let account = open_account(matches)?;
+    let dry_run = matches.is_present("dry-run");
+    let expired = matches.is_present("expired");
+    let unused = matches.is_present("unused");
+    let mut tokens = match matches.values_of("LINK") {
+        Some(links) => links.map(link_token).collect::<Result<Vec<_>>>()?,
+        None => Vec::new(),
+    };
+    if expired || unused {
+        tokens.extend(
+            account
+                .list_links()?
+                .into_iter()
+                .filter(|info| (expired && info.is_expired) || (unused && info.view_cnt == 0))
+                .map(|info| info.token),
+        );
+    } else if tokens.is_empty() {
+        return Err(Error::Usage(
+            "give the links to delete, --expired or --unused".into(),
+        ));
+    }
+    // A link given explicitly may also be expired or unused.
+    let mut seen = BTreeSet::new();
+    tokens.retain(|token| seen.insert(token.clone()));
+    for token in tokens {
+        let info = if dry_run {
+            account.link_info(&token)?
+        } else {
+            account.delete_link(&token)?
+        };
+        print_link(format, &info);
+    }
+    Ok(())
+}
+
+fn handle_share_create(matches: &ArgMatches, kind: &str) -> Result<()> {
+    let format = OutputFormat::from_matches(matches);
+    let account = open_account(matches)?;
+    let remote = RemotePath::parse(matches.value_of("REMOTE").unwrap())?;
+    let password = if matches.is_present("password")
+        || matches.is_present("password-file")
+        || matches.is_present("password-stdin")
+    {
+        Some(read_password(matches)?)
+    } else {
+        None
+    };
+    let expire_days = match matches.value_of("expire-days").map(str::parse) {
+        None => None,
+        Some(Ok(days)) if days > 0 => Some(days),
+        Some(_) => {
+            return Err(Error::Usage(
+                "expiration must be a positive number of days".into(),
+            ))
+        }
+    };
+    let options = LinkOptions {
+        password,
+        expire_days,
+        permissions: Some(LinkPermissions {
+            can_edit: matches.is_present("can-edit"),
+            can_download: !matches.is_present("no-download"),
+        }),
+    };
+    let lib = account.find_library(&remote.library)?;
+    let info = match kind {
+        "upload" => account.create_upload_link(&lib.id, &remote.path, &options)?,
+        _ => account.create_download_link(&lib.id, &remote.path, &options)?,
+    };
+    let record = LinkRecord {
+        password_protected: options.password.is_some(),
+        ..LinkRecord::new(&info)
+    };
+    format.print(&record, &[&info.link]);
+    Ok(())
+}
+
+/// A file operation, as printed by `--output json`.
+#[derive(Serialize)]
+struct FileOpRecord<'a> {
+    operation: &'a str,
+    library: &'a str,
+    path: &'a str,
+    /// Where `mv`, `cp` and `rename` put the entry.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    to_library: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    to_path: Option<String>,
+}
+
+impl<'a> FileOpRecord<'a> {
+    fn print(&self, format: OutputFormat) {
+        let from = format!("{}:{}", self.library, self.path);
+        match (self.to_library, &self.to_path) {
+            (Some(to_library), Some(to_path)) => {
+                format.print(self, &[&from, &format!("{}:{}", to_library, to_path)])
+            }
+            _ => format.print(self, &[&from]),
+        }
+    }
+}
+
+/// Resolves `<library>:/<path>` arguments, listing the libraries only once.
+fn resolve_remotes<'a>(
+    libraries: &'a [Library],
+    specs: &[&str],
+) -> Result<Vec<(&'a Library, RemotePath)>> {
+    specs
+        .iter()
+        .map(|spec| {
+            let remote = RemotePath::parse(spec)?;
+            Ok((find_library(libraries, &remote.library)?, remote))
+        })
+        .collect()
+}
+
+/// Checks that `remote` exists and is not the root of its library.
+fn check_entry(account: &Account, lib: &Library, remote: &RemotePath) -> Result<Dirent> {
+    if remote.path == "/" {
+        return Err(Error::Usage(format!(
+            "{}: cannot operate on the root of a library",
+            lib.name
+        )));
+    }
+    account.stat(&lib.id, &remote.path)?.ok_or_else(|| {
+        Error::Usage(format!(
+            "{}:{}: no such file or directory",
+            lib.name, remote.path
         ))
-        .unwrap();
+    })
+}
+
+/// Groups entries by their library and parent directory, which batch
+/// operations work on, keeping the order of the arguments.
+fn group_by_parent<'a>(
+    remotes: &'a [(&'a Library, RemotePath)],
+) -> Vec<(&'a Library, &'a str, Vec<&'a str>)> {
+    let mut groups: Vec<(&Library, &str, Vec<&str>)> = Vec::new();
+    for (lib, remote) in remotes {
+        match groups
+            .iter_mut()
+            .find(|(group_lib, parent, _)| group_lib.id == lib.id && *parent == remote.parent())
+        {
+            Some((_, _, names)) => names.push(remote.name()),
+            None => groups.push((lib, remote.parent(), vec![remote.name()])),
+        }
+    }
+    groups
+}
+
+fn handle_mkdir(matches: &ArgMatches) -> Result<()> {
+    let format = OutputFormat::from_matches(matches);
+    let account = open_account(matches)?;
+    let libraries = account.list_libraries()?;
+    let specs: Vec<&str> = matches.values_of("REMOTE").unwrap().collect();
+    for (lib, remote) in resolve_remotes(&libraries, &specs)? {
+        account.mkdir(&lib.id, &remote.path, matches.is_present("parents"))?;
+        FileOpRecord {
+            operation: "mkdir",
+            library: &lib.name,
+            path: &remote.path,
+            to_library: None,
+            to_path: None,
+        }
+        .print(format);
     }
-    for script in document.select(&Selector::parse("script").unwrap()) {
-        for text in script.text() {
-            if let Some(caps) = RE.captures(text) {
-                return Ok(caps.get(1).unwrap().into());
+    Ok(())
+}
+
+fn handle_rm(matches: &ArgMatches) -> Result<()> {
+    let format = OutputFormat::from_matches(matches);
+    let account = open_account(matches)?;
+    let libraries = account.list_libraries()?;
+    let specs: Vec<&str> = matches.values_of("REMOTE").unwrap().collect();
+    let remotes = resolve_remotes(&libraries, &specs)?;
+    for (lib, remote) in &remotes {
+        check_entry(&account, lib, remote)?;
+    }
+    for (lib, parent, names) in group_by_parent(&remotes) {
+        account.delete(&lib.id, parent, &names)?;
+        for name in names {
+            FileOpRecord {
+                operation: "rm",
+                library: &lib.name,
+                path: &join_path(parent, name),
+                to_library: None,
+                to_path: None,
             }
+            .print(format);
         }
     }
-    Err("invalid token/password".into())
+    Ok(())
 }
 
-#[derive(Deserialize)]
-struct UploadUrl {
-    url: String,
+fn join_path(dir: &str, name: &str) -> String {
+    format!("{}/{}", dir.trim_end_matches('/'), name)
 }
 
-fn get_upload_url(
-    client: &Client,
-    token: &str,
-    repo_id: &str,
-) -> Result<String, Box<dyn std::error::Error>> {
-    let url = format!(
-        "{}/ajax/u/d/{}/upload/?r={}&_={}",
-        BASE_URL,
-        token,
-        repo_id,
-        SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis()
-    );
-    let upload_url: UploadUrl = client
-        .get(&url)
-        .header("X-Requested-With", "XMLHttpRequest")
-        .send()?
-        .json()?;
-    Ok(upload_url.url)
+/// Moves (`mv`) or copies (`cp`) the sources into the destination
+/// directory, which may be in another library.
+fn handle_transfer(matches: &ArgMatches, operation: &str) -> Result<()> {
+    let format = OutputFormat::from_matches(matches);
+    let account = open_account(matches)?;
+    let libraries = account.list_libraries()?;
+    let mut specs: Vec<&str> = matches.values_of("REMOTE").unwrap().collect();
+    let dest_spec = specs.pop().unwrap();
+    if specs.is_empty() {
+        return Err(Error::Usage(format!(
+            "{} needs sources and a destination directory",
+            operation
+        )));
+    }
+    let (dest_lib, dest) = resolve_remotes(&libraries, &[dest_spec])?.remove(0);
+    match account.stat(&dest_lib.id, &dest.path)? {
+        Some(dirent) if dirent.is_dir() => {}
+        _ => {
+            return Err(Error::Usage(format!(
+                "{}:{}: not a directory; use rename to rename entries",
+                dest_lib.name, dest.path
+            )))
+        }
+    }
+    let remotes = resolve_remotes(&libraries, &specs)?;
+    for (lib, remote) in &remotes {
+        check_entry(&account, lib, remote)?;
+    }
+    for (lib, parent, names) in group_by_parent(&remotes) {
+        if operation == "mv" {
+            account.move_items(&lib.id, parent, &names, &dest_lib.id, &dest.path)?;
+        } else {
+            account.copy_items(&lib.id, parent, &names, &dest_lib.id, &dest.path)?;
+        }
+        for name in names {
+            FileOpRecord {
+                operation,
+                library: &lib.name,
+                path: &join_path(parent, name),
+                to_library: Some(&dest_lib.name),
+                to_path: Some(join_path(&dest.path, name)),
+            }
+            .print(format);
+        }
+    }
+    Ok(())
 }
 
-#[derive(Deserialize)]
-struct FileUploadResp {
-    name: String,
-    id: String,
-    size: usize,
-}
-
-fn upload_file(
-    client: &Client,
-    url: &str,
-    file_path: &str,
-) -> Result<FileUploadResp, Box<dyn std::error::Error>> {
-    let form = reqwest::blocking::multipart::Form::new()
-        .text("parent_dir", "/")
-        .file("file", file_path)?;
-    let mut resps: Vec<FileUploadResp> = client.post(url).multipart(form).send()?.json()?;
-    resps.pop().ok_or("file upload failed".into())
-}
-
-fn handle_upload(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
-    let token = matches.value_of("TOKEN").unwrap();
-    let file_path = matches.value_of("FILEPATH").unwrap();
-    if !Path::new(file_path).exists() {
-        return Err("no such file".into());
-    }
-    let client = Client::builder().timeout(None).cookie_store(true).build()?;
-    let document = get_first_page(&client, token)?;
-    let password = rpassword::prompt_password_stdout("Password: ").unwrap();
-    let document = post_password(&client, token, extract_token(&document)?, &password)?;
-    let upload_url = get_upload_url(&client, token, extract_repo_id(&document)?)?;
-    let resp = upload_file(&client, &upload_url, file_path)?;
-    println!("{} {} {}", resp.id, resp.name, resp.size);
+fn handle_rename(matches: &ArgMatches) -> Result<()> {
+    let format = OutputFormat::from_matches(matches);
+    let account = open_account(matches)?;
+    let remote = RemotePath::parse(matches.value_of("REMOTE").unwrap())?;
+    let new_name = matches.value_of("NEW_NAME").unwrap();
+    let lib = account.find_library(&remote.library)?;
+    check_entry(&account, &lib, &remote)?;
+    account.rename(&lib.id, &remote.path, new_name)?;
+    FileOpRecord {
+        operation: "rename",
+        library: &lib.name,
+        path: &remote.path,
+        to_library: Some(&lib.name),
+        to_path: Some(join_path(remote.parent(), new_name)),
+    }
+    .print(format);
     Ok(())
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// A step of a sync, as printed by `--output json`.
+#[derive(Serialize)]
+struct SyncRecord<'a> {
+    #[serde(flatten)]
+    action: &'a SyncAction,
+    library: &'a str,
+    dry_run: bool,
+}
+
+fn handle_sync(matches: &ArgMatches) -> Result<()> {
+    let format = OutputFormat::from_matches(matches);
+    let account = open_account(matches)?;
+    let local = Path::new(matches.value_of("LOCAL_DIR").unwrap());
+    let remote = RemotePath::parse(matches.value_of("REMOTE").unwrap())?;
+    let lib = account.find_library(&remote.library)?;
+    let chunk_size = chunk_size(matches)?;
+    let dry_run = matches.is_present("dry-run");
+    if matches.is_present("bidirectional") {
+        return sync_two_way(
+            &account,
+            &lib,
+            local,
+            &remote.path,
+            chunk_size,
+            dry_run,
+            format,
+        );
+    }
+    let options = SyncOptions {
+        compare: if matches.is_present("checksum") {
+            Compare::Checksum
+        } else {
+            Compare::SizeMtime
+        },
+        delete: matches.is_present("delete"),
+    };
+    for action in account.plan_sync(local, &lib.id, &remote.path, &options)? {
+        if !dry_run {
+            let report = sync_report(&lib, &action, format);
+            account.apply_sync(&lib.id, &action, chunk_size, &(report.clone() as _))?;
+            report.finish();
+        }
+        print_sync(format, &lib, &action, dry_run);
+    }
+    Ok(())
+}
+
+/// Where the state of `kind`, `sync` or `watch`, for the remote `target`
+/// and local directory `local` lives: in the user's data directory, named
+/// after a digest of both.
+fn state_path(kind: &str, target: &str, local: &Path) -> Result<PathBuf> {
+    let local = local
+        .canonicalize()
+        .map_err(|e| Error::Usage(format!("{}: {}", local.display(), e)))?;
+    let key = format!("{}\n{}", target, local.display());
+    let data_dir =
+        dirs::data_dir().ok_or_else(|| Error::Local("cannot locate the data directory".into()))?;
+    Ok(data_dir
+        .join("seaf-web")
+        .join(kind)
+        .join(format!("{}.json", sha1_smol::Sha1::from(key).digest())))
+}
+
+fn sync_two_way(
+    account: &Account,
+    lib: &Library,
+    local: &Path,
+    dir: &str,
+    chunk_size: u64,
+    dry_run: bool,
+    format: OutputFormat,
+) -> Result<()> {
+    let target = format!("{}\n{}\n{}", account.server(), lib.id, dir);
+    let state_path = state_path("sync", &target, local)?;
+    let mut state = SyncState::load(&state_path)?;
+    let plan = account.plan_two_way(local, &lib.id, dir, &mut state)?;
+    if dry_run {
+        for action in &plan {
+            print_sync(format, lib, action, dry_run);
+        }
+        return Ok(());
+    }
+    let mut result = Ok(());
+    for action in &plan {
+        let report = sync_report(lib, action, format);
+        result = account.apply_two_way(
+            &lib.id,
+            dir,
+            action,
+            chunk_size,
+            &(report.clone() as _),
+            &mut state,
+        );
+        report.finish();
+        if result.is_err() {
+            break;
+        }
+        print_sync(format, lib, action, dry_run);
+    }
+    // What was synced before a failure need not be synced again.
+    state.save(&state_path)?;
+    result
+}
+
+/// Reports the transfers of a step of a sync; other steps report nothing.
+fn sync_report(lib: &Library, action: &SyncAction, format: OutputFormat) -> Arc<ProgressReport> {
+    let path = format!("{}:{}", lib.name, action.remote());
+    Arc::new(match action {
+        SyncAction::Upload { size, .. } | SyncAction::Download { size, .. } => {
+            ProgressReport::new(&path, Some(*size), format)
+        }
+        SyncAction::Conflict { .. } => ProgressReport::new(&path, None, format),
+        _ => ProgressReport::silent(format),
+    })
+}
+
+fn print_sync(format: OutputFormat, lib: &Library, action: &SyncAction, dry_run: bool) {
+    let record = SyncRecord {
+        action,
+        library: &lib.name,
+        dry_run,
+    };
+    let path = format!("{}:{}", lib.name, action.remote());
+    match action {
+        SyncAction::Mkdir { .. } => format.print(&record, &[&"mkdir", &path]),
+        SyncAction::Delete { .. } => format.print(&record, &[&"delete", &path]),
+        SyncAction::Upload { changed, .. } => format.print(
+            &record,
+            &[&"upload", &path, &if *changed { "changed" } else { "new" }],
+        ),
+        SyncAction::Download { .. } => format.print(&record, &[&"download", &path]),
+        SyncAction::DeleteLocal { local, .. } => {
+            format.print(&record, &[&"delete-local", &local.display()])
+        }
+        SyncAction::Conflict { copy, .. } => {
+            format.print(&record, &[&"conflict", &path, &copy.display()])
+        }
+    }
+}
+
+fn handle_download(matches: &ArgMatches) -> Result<()> {
+    let format = OutputFormat::from_matches(matches);
+    let link = Link::parse(
+        matches.value_of("LINK").unwrap(),
+        &[LinkKind::Dir, LinkKind::File],
+    )?;
+    let dest = Path::new(matches.value_of("DEST").unwrap_or("."));
+    let mut share_link = ShareLink::open_with_client(
+        link::client()?,
+        retry_policy(matches)?,
+        &link_server(&link, matches)?,
+        link.kind,
+        &link.token,
+    )?;
+    if share_link.needs_password() {
+        share_link.authenticate(&read_password(matches)?)?;
+    }
+    let mut print = |file: DownloadedFile| {
+        let record = DownloadRecord {
+            server: share_link.server(),
+            token: share_link.token(),
+            file: &file,
+        };
+        format.print(&record, &[&file.local_path.display(), &file.size]);
+    };
+    match link.kind {
+        LinkKind::File => print(share_link.download_file(dest)?),
+        _ => share_link.download_dir("/", dest, &mut print)?,
+    }
+    Ok(())
+}
+
+fn password_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("password-file")
+            .long("password-file")
+            .value_name("FILE")
+            .help("Reads the password from the first line of FILE"),
+        Arg::with_name("password-stdin")
+            .long("password-stdin")
+            .conflicts_with("password-file")
+            .help("Reads the password from the first line of stdin"),
+    ]
+}
+
+fn create_link_args() -> Vec<Arg<'static, 'static>> {
+    let mut args = vec![
+        Arg::with_name("REMOTE")
+            .required(true)
+            .help("Path to share, <library>:/<path>, the library by name or id"),
+        Arg::with_name("password")
+            .long("password")
+            .help("Protects the link with a password, prompted for or read from SEAF_WEB_PASSWORD"),
+        Arg::with_name("expire-days")
+            .long("expire-days")
+            .value_name("DAYS")
+            .help("Makes the link expire after this many days"),
+    ];
+    args.extend(password_args());
+    args
+}
+
+fn chunk_size_arg() -> Arg<'static, 'static> {
+    Arg::with_name("chunk-size")
+        .long("chunk-size")
+        .value_name("MIB")
+        .default_value("8")
+        .help("Uploads larger files in resumable chunks of this many MiB")
+}
+
+fn remotes_arg(help: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name("REMOTE")
+        .required(true)
+        .multiple(true)
+        .help(help)
+}
+
+const PASSWORD_HELP: &str = "The link password can also be given in the SEAF_WEB_PASSWORD \
+                             environment variable; links without a password never prompt.";
+
+fn main() {
     let matches = App::new(crate_name!())
-        .setting(AppSettings::ArgRequiredElseHelp)
+        .setting(AppSettings::SubcommandRequiredElseHelp)
         .version(crate_version!())
         .about(crate_description!())
         .author(crate_authors!())
+        .after_help(EXIT_CODES)
+        .arg(
+            Arg::with_name("server")
+                .long("server")
+                .value_name("URL")
+                .env("SEAF_WEB_SERVER")
+                .global(true)
+                .help("Seafile server base URL"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .value_name("FORMAT")
+                .possible_values(&["text", "json", "tsv"])
+                .default_value("text")
+                .global(true)
+                .help("Output format of results and errors"),
+        )
+        .arg(
+            Arg::with_name("retries")
+                .long("retries")
+                .value_name("N")
+                .default_value("3")
+                .global(true)
+                .help("Retries requests failing with network errors, 5xx or 429 this many times"),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .value_name("FILE")
+                .global(true)
+                .help("Config file [default: <config dir>/seaf-web/config.toml]"),
+        )
         .subcommand(
             SubCommand::with_name("upload")
-                .about("Uploads local file to cloud")
-                .arg(Arg::with_name("TOKEN").required(true))
-                .arg(Arg::with_name("FILEPATH").required(true)),
+                .about("Uploads local files and directories to cloud")
+                .arg(
+                    Arg::with_name("LINK")
+                        .required(true)
+                        .help("Upload link token or URL (<server>/u/d/<token>/)"),
+                )
+                .args(&password_args())
+                .arg(chunk_size_arg())
+                .arg(
+                    Arg::with_name("dest")
+                        .long("dest")
+                        .value_name("REMOTE_DIR")
+                        .help(
+                            "Uploads into this directory of the link, \
+                             creating it if it does not exist",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("on-conflict")
+                        .long("on-conflict")
+                        .value_name("POLICY")
+                        .possible_values(&["rename", "overwrite", "fail"])
+                        .default_value("rename")
+                        .help(
+                            "What to do when a file of the same name already exists. \
+                             Upload links cannot look for existing files: they cannot skip \
+                             them, and fail only stops once the server stored the upload \
+                             under a new name, which it reports",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("jobs")
+                        .long("jobs")
+                        .short("j")
+                        .value_name("N")
+                        .default_value("1")
+                        .help("Uploads this many files at once"),
+                )
+                .arg(
+                    Arg::with_name("name")
+                        .long("name")
+                        .value_name("NAME")
+                        .help("Name of the file uploaded from stdin"),
+                )
+                .arg(
+                    Arg::with_name("FILEPATH")
+                        .required_unless("watch")
+                        .conflicts_with("watch")
+                        .multiple(true)
+                        .help(
+                            "Files or directories to upload, directories recursively, \
+                             or - to upload stdin as --name. Stdin longer than a chunk is \
+                             copied to a temporary file first",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("watch")
+                        .long("watch")
+                        .value_name("DIR")
+                        .conflicts_with("name")
+                        .help(
+                            "Keeps uploading the files written or moved into this directory, \
+                             up to --jobs at once; restarts skip what was uploaded",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("debounce")
+                        .long("debounce")
+                        .value_name("SECS")
+                        .requires("watch")
+                        .help(
+                            "Waits this long after a file was written before uploading it \
+                             [default: 2]",
+                        ),
+                )
+                .after_help(PASSWORD_HELP),
+        )
+        .subcommand(
+            SubCommand::with_name("download")
+                .about("Downloads a shared file or mirrors a shared folder")
+                .arg(
+                    Arg::with_name("LINK")
+                        .required(true)
+                        .help("Share link URL (<server>/d/<token>/ or <server>/f/<token>/)"),
+                )
+                .args(&password_args())
+                .arg(Arg::with_name("DEST").help("Local file or directory to save to [default: .]"))
+                .after_help(PASSWORD_HELP),
+        )
+        .subcommand(
+            SubCommand::with_name("login")
+                .about("Logs in to an account and saves its API token for later commands")
+                .arg(
+                    Arg::with_name("username")
+                        .long("username")
+                        .short("u")
+                        .value_name("EMAIL")
+                        .help("Account to log in to; prompted for if not given"),
+                )
+                .args(&password_args())
+                .arg(
+                    Arg::with_name("otp")
+                        .long("otp")
+                        .value_name("CODE")
+                        .help("Two-factor authentication code; prompted for if needed"),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .help("Logs in again even if the saved token is still valid"),
+                )
+                .after_help(
+                    "The password can also be given in the SEAF_WEB_PASSWORD environment \
+                     variable. The token is saved in credentials.toml next to the config file.",
+                ),
         )
-        .get_matches();
-    if let Some(matches) = matches.subcommand_matches("upload") {
-        handle_upload(&matches)?;
+        .subcommand(
+            SubCommand::with_name("ls")
+                .about("Lists the libraries of the account, or a directory of a library")
+                .arg(
+                    Arg::with_name("REMOTE")
+                        .help("Directory to list, <library>:/<path>, the library by name or id"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("share")
+                .about("Manages the share links of the account")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("create-upload-link")
+                        .about("Creates an upload link for a directory and prints its URL")
+                        .args(&create_link_args()),
+                )
+                .subcommand(
+                    SubCommand::with_name("create-download-link")
+                        .about("Creates a download link for a file or directory and prints its URL")
+                        .args(&create_link_args())
+                        .arg(
+                            Arg::with_name("no-download")
+                                .long("no-download")
+                                .help("Only lets visitors preview the files"),
+                        )
+                        .arg(
+                            Arg::with_name("can-edit")
+                                .long("can-edit")
+                                .help("Lets visitors edit files online"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about(
+                            "Lists the download and upload links of the account: \
+                             kind, token, views, expiry, password protection and path",
+                        )
+                        .arg(
+                            Arg::with_name("REMOTE")
+                                .help("Only lists links within <library>:/<path>"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("info")
+                        .about("Describes a share link")
+                        .arg(
+                            Arg::with_name("LINK")
+                                .required(true)
+                                .help("Token or URL of the link"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("delete")
+                        .about("Revokes share links and prints them")
+                        .arg(
+                            Arg::with_name("LINK")
+                                .multiple(true)
+                                .help("Tokens or URLs of the links"),
+                        )
+                        .arg(
+                            Arg::with_name("expired")
+                                .long("expired")
+                                .help("Also revokes all expired links"),
+                        )
+                        .arg(
+                            Arg::with_name("unused")
+                                .long("unused")
+                                .help("Also revokes all links that were never opened"),
+                        )
+                        .arg(
+                            Arg::with_name("dry-run")
+                                .long("dry-run")
+                                .help("Only prints the links that would be revoked"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("mkdir")
+                .about("Creates directories in libraries")
+                .arg(
+                    Arg::with_name("parents")
+                        .long("parents")
+                        .short("p")
+                        .help("Creates missing parents, and accepts existing directories"),
+                )
+                .arg(remotes_arg("Directories to create, <library>:/<path>")),
+        )
+        .subcommand(
+            SubCommand::with_name("rm")
+                .about("Deletes files and directories, directories with their contents")
+                .arg(remotes_arg("Entries to delete, <library>:/<path>")),
+        )
+        .subcommand(
+            SubCommand::with_name("mv")
+                .about("Moves files and directories into a directory, across libraries too")
+                .arg(remotes_arg(
+                    "Entries to move, then the destination directory",
+                )),
+        )
+        .subcommand(
+            SubCommand::with_name("cp")
+                .about("Copies files and directories into a directory, across libraries too")
+                .arg(remotes_arg(
+                    "Entries to copy, then the destination directory",
+                )),
+        )
+        .subcommand(
+            SubCommand::with_name("rename")
+                .about("Renames a file or directory")
+                .arg(
+                    Arg::with_name("REMOTE")
+                        .required(true)
+                        .help("Entry to rename, <library>:/<path>"),
+                )
+                .arg(
+                    Arg::with_name("NEW_NAME")
+                        .required(true)
+                        .help("New name, without a directory"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("sync")
+                .about("Mirrors a local directory to a directory of a library")
+                .long_about(
+                    "Mirrors a local directory to a directory of a library: creates missing \
+                     directories and uploads new files and files that changed, that is differ \
+                     in size or were modified locally after their last upload.",
+                )
+                .arg(
+                    Arg::with_name("LOCAL_DIR")
+                        .required(true)
+                        .help("Local directory to mirror"),
+                )
+                .arg(
+                    Arg::with_name("REMOTE")
+                        .required(true)
+                        .help("Remote directory, <library>:/<path>, created if missing"),
+                )
+                .arg(
+                    Arg::with_name("delete")
+                        .long("delete")
+                        .help("Deletes remote files and directories missing locally"),
+                )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .short("n")
+                        .help("Prints the plan without changing anything"),
+                )
+                .arg(
+                    Arg::with_name("bidirectional")
+                        .long("bidirectional")
+                        .conflicts_with_all(&["delete", "checksum"])
+                        .help(
+                            "Also downloads remote changes, and keeps conflicting local \
+                             versions as conflicted copies",
+                        ),
+                )
+                .arg(Arg::with_name("checksum").long("checksum").short("c").help(
+                    "Compares files of the same size by content instead of modification \
+                     time, downloading the remote ones",
+                ))
+                .arg(chunk_size_arg()),
+        )
+        .get_matches_safe()
+        .unwrap_or_else(|e| {
+            if !e.use_stderr() {
+                e.exit();
+            }
+            eprintln!("{}", e.message);
+            process::exit(Error::Usage(String::new()).exit_code());
+        });
+    let (name, matches) = matches.subcommand();
+    let matches = matches.unwrap();
+    let result = match name {
+        "upload" => handle_upload(matches),
+        "download" => handle_download(matches),
+        "login" => handle_login(matches),
+        "ls" => handle_ls(matches),
+        "share" => handle_share(matches),
+        "mkdir" => handle_mkdir(matches),
+        "rm" => handle_rm(matches),
+        "mv" => handle_transfer(matches, "mv"),
+        "cp" => handle_transfer(matches, "cp"),
+        "rename" => handle_rename(matches),
+        "sync" => handle_sync(matches),
+        _ => unreachable!(),
+    };
+    if let Err(e) = result {
+        OutputFormat::from_matches(matches).print_error(&e);
+        process::exit(e.exit_code());
     }
-    Ok(())
 }
@@ -0,0 +1,62 @@
+use reqwest::blocking::multipart::Part;
+use std::{
+    io::{self, Read},
+    sync::Arc,
+};
+
+/// Receives the progress of a transfer.
+pub trait Progress: Send + Sync {
+    /// The transfer continues at `position`, skipping the bytes the server
+    /// kept from an earlier attempt.
+    fn resume_at(&self, _position: u64) {}
+
+    /// A failed attempt is retried from `position`, taking back the bytes
+    /// it reported after it.
+    fn rewind(&self, _position: u64) {}
+
+    /// `delta` more bytes were sent.
+    fn inc(&self, delta: u64);
+}
+
+/// Ignores all progress.
+pub struct NoProgress;
+
+impl Progress for NoProgress {
+    fn inc(&self, _delta: u64) {}
+}
+
+/// Counts the bytes read through it into a [`Progress`].
+struct ProgressReader<R> {
+    inner: R,
+    progress: Arc<dyn Progress>,
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.progress.inc(n as u64);
+        Ok(n)
+    }
+}
+
+/// A reader whose reads, e.g. of a download, are reported to `progress`.
+pub(crate) fn progress_reader<R: Read>(reader: R, progress: &Arc<dyn Progress>) -> impl Read {
+    ProgressReader {
+        inner: reader,
+        progress: progress.clone(),
+    }
+}
+
+/// A multipart file part whose upload is reported to `progress`.
+pub(crate) fn progress_part<R: Read + Send + 'static>(
+    reader: R,
+    length: u64,
+    file_name: String,
+    progress: &Arc<dyn Progress>,
+) -> Part {
+    let reader = ProgressReader {
+        inner: reader,
+        progress: progress.clone(),
+    };
+    Part::reader_with_length(reader, length).file_name(file_name)
+}
@@ -0,0 +1,328 @@
+use crate::{
+    account::Account,
+    error::{check_response, Error, Result},
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+/// A library (repository) the account can access.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Library {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub owner: String,
+    #[serde(default)]
+    pub size: u64,
+    /// Last modification, in seconds since the epoch.
+    #[serde(default)]
+    pub mtime: i64,
+    /// `r` or `rw`.
+    #[serde(default)]
+    pub permission: String,
+    #[serde(default)]
+    pub encrypted: bool,
+}
+
+/// An entry of a directory in a library.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Dirent {
+    pub name: String,
+    /// `file` or `dir`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub id: String,
+    /// Size in bytes; 0 for directories.
+    #[serde(default)]
+    pub size: u64,
+    /// Last modification, in seconds since the epoch.
+    #[serde(default)]
+    pub mtime: i64,
+    #[serde(default)]
+    pub permission: String,
+}
+
+impl Dirent {
+    pub fn is_dir(&self) -> bool {
+        self.kind == "dir"
+    }
+}
+
+/// A path in a library as given by the user, `<library>:/<path>`, where the
+/// library is named by its name or id.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RemotePath {
+    pub library: String,
+    /// Absolute, without a trailing slash except for the root.
+    pub path: String,
+}
+
+impl RemotePath {
+    /// Parses `<library>:/<path>`; a bare `<library>` is its root.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (library, path) = match spec.find(':') {
+            Some(colon) => (&spec[..colon], &spec[colon + 1..]),
+            None => (spec, "/"),
+        };
+        if library.is_empty() {
+            return Err(Error::Usage(format!(
+                "invalid remote path {:?}: expected <library>:/<path>",
+                spec
+            )));
+        }
+        Ok(RemotePath {
+            library: library.to_string(),
+            path: normalize_path(path),
+        })
+    }
+}
+
+impl RemotePath {
+    /// The directory containing the path; the root is its own parent.
+    pub fn parent(&self) -> &str {
+        match self.path.rfind('/') {
+            Some(0) | None => "/",
+            Some(slash) => &self.path[..slash],
+        }
+    }
+
+    /// The last component of the path; empty for the root.
+    pub fn name(&self) -> &str {
+        &self.path[self.path.rfind('/').map_or(0, |slash| slash + 1)..]
+    }
+}
+
+/// Finds a library by id, or else by name, in `libraries`.
+pub fn find_library<'a>(libraries: &'a [Library], library: &str) -> Result<&'a Library> {
+    if let Some(lib) = libraries.iter().find(|lib| lib.id == library) {
+        return Ok(lib);
+    }
+    let mut found = libraries.iter().filter(|lib| lib.name == library);
+    match (found.next(), found.next()) {
+        (Some(lib), None) => Ok(lib),
+        (None, _) => Err(Error::Usage(format!("no library named {:?}", library))),
+        _ => Err(Error::Usage(format!(
+            "several libraries are named {:?}, use the id instead",
+            library
+        ))),
+    }
+}
+
+#[derive(Serialize)]
+struct BatchDelete<'a> {
+    repo_id: &'a str,
+    parent_dir: &'a str,
+    dirents: &'a [&'a str],
+}
+
+#[derive(Serialize)]
+struct BatchTransfer<'a> {
+    src_repo_id: &'a str,
+    src_parent_dir: &'a str,
+    src_dirents: &'a [&'a str],
+    dst_repo_id: &'a str,
+    dst_parent_dir: &'a str,
+}
+
+/// Makes `path` absolute and drops empty components and trailing slashes.
+pub(crate) fn normalize_path(path: &str) -> String {
+    let components: Vec<&str> = path
+        .split('/')
+        .filter(|component| !component.is_empty() && *component != ".")
+        .collect();
+    format!("/{}", components.join("/"))
+}
+
+impl Account {
+    /// Lists the libraries the account owns or has been shared.
+    pub fn list_libraries(&self) -> Result<Vec<Library>> {
+        Ok(self
+            .send("/api2/repos/", |client, url| client.get(url))?
+            .json()?)
+    }
+
+    /// Finds a library by id, or else by name.
+    pub fn find_library(&self, library: &str) -> Result<Library> {
+        Ok(find_library(&self.list_libraries()?, library)?.clone())
+    }
+
+    /// Lists directory `path` of library `repo_id`.
+    pub fn list_dir(&self, repo_id: &str, path: &str) -> Result<Vec<Dirent>> {
+        let path = normalize_path(path);
+        Ok(self
+            .send(&format!("/api2/repos/{}/dir/", repo_id), |client, url| {
+                client.get(url).query(&[("p", path.as_str())])
+            })?
+            .json()?)
+    }
+
+    /// Describes file or directory `path` of library `repo_id`, or returns
+    /// `None` if there is nothing at `path`.
+    pub fn stat(&self, repo_id: &str, path: &str) -> Result<Option<Dirent>> {
+        let path = RemotePath {
+            library: repo_id.to_string(),
+            path: normalize_path(path),
+        };
+        if path.path == "/" {
+            return Ok(Some(Dirent {
+                name: String::new(),
+                kind: "dir".into(),
+                id: String::new(),
+                size: 0,
+                mtime: 0,
+                permission: String::new(),
+            }));
+        }
+        let resp = self
+            .send_unchecked(&format!("/api2/repos/{}/dir/", repo_id), |client, url| {
+                client.get(url).query(&[("p", path.parent())])
+            })?;
+        // The parent directory does not exist either.
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let dirents: Vec<Dirent> = check_response(resp)?.json()?;
+        Ok(dirents
+            .into_iter()
+            .find(|dirent| dirent.name == path.name()))
+    }
+
+    /// Creates directory `path` in library `repo_id`, and with `parents` its
+    /// missing parents; existing directories are fine then.
+    pub fn mkdir(&self, repo_id: &str, path: &str, parents: bool) -> Result<()> {
+        let path = normalize_path(path);
+        if parents {
+            let mut prefix = String::new();
+            for component in path.split('/').filter(|component| !component.is_empty()) {
+                prefix.push('/');
+                prefix.push_str(component);
+                match self.stat(repo_id, &prefix)? {
+                    Some(dirent) if dirent.is_dir() => {}
+                    Some(_) => {
+                        return Err(Error::Conflict(format!("{} is a file", prefix)));
+                    }
+                    None => self.create_dir(repo_id, &prefix)?,
+                }
+            }
+            return Ok(());
+        }
+        // The server would pick another name rather than fail.
+        if self.stat(repo_id, &path)?.is_some() {
+            return Err(Error::Conflict(format!("{} already exists", path)));
+        }
+        self.create_dir(repo_id, &path)
+    }
+
+    fn create_dir(&self, repo_id: &str, path: &str) -> Result<()> {
+        self.send(
+            &format!("/api/v2.1/repos/{}/dir/", repo_id),
+            |client, url| {
+                client
+                    .post(url)
+                    .query(&[("p", path)])
+                    .form(&[("operation", "mkdir")])
+            },
+        )?;
+        Ok(())
+    }
+
+    /// Deletes entries `names` of directory `parent_dir` in library
+    /// `repo_id`, files and directories alike.
+    pub fn delete(&self, repo_id: &str, parent_dir: &str, names: &[&str]) -> Result<()> {
+        let parent_dir = normalize_path(parent_dir);
+        let form = BatchDelete {
+            repo_id,
+            parent_dir: &parent_dir,
+            dirents: names,
+        };
+        self.send("/api/v2.1/repos/batch-delete-item/", |client, url| {
+            client.delete(url).json(&form)
+        })?;
+        Ok(())
+    }
+
+    /// Renames file or directory `path` of library `repo_id` to `new_name`.
+    pub fn rename(&self, repo_id: &str, path: &str, new_name: &str) -> Result<()> {
+        if new_name.is_empty() || new_name.contains('/') {
+            return Err(Error::Usage(format!("invalid file name {:?}", new_name)));
+        }
+        let path = normalize_path(path);
+        let dirent = self
+            .stat(repo_id, &path)?
+            .ok_or_else(|| Error::Usage(format!("{}: no such file or directory", path)))?;
+        let kind = if dirent.is_dir() { "dir" } else { "file" };
+        self.send(
+            &format!("/api/v2.1/repos/{}/{}/", repo_id, kind),
+            |client, url| {
+                client
+                    .post(url)
+                    .query(&[("p", path.as_str())])
+                    .form(&[("operation", "rename"), ("newname", new_name)])
+            },
+        )?;
+        Ok(())
+    }
+
+    /// Moves entries `names` of `src_parent_dir` in library `src_repo_id`
+    /// into directory `dst_parent_dir` of library `dst_repo_id`, which may
+    /// be another library.
+    pub fn move_items(
+        &self,
+        src_repo_id: &str,
+        src_parent_dir: &str,
+        names: &[&str],
+        dst_repo_id: &str,
+        dst_parent_dir: &str,
+    ) -> Result<()> {
+        self.transfer(
+            "/api/v2.1/repos/sync-batch-move-item/",
+            src_repo_id,
+            src_parent_dir,
+            names,
+            dst_repo_id,
+            dst_parent_dir,
+        )
+    }
+
+    /// Copies entries like [`Account::move_items`] moves them.
+    pub fn copy_items(
+        &self,
+        src_repo_id: &str,
+        src_parent_dir: &str,
+        names: &[&str],
+        dst_repo_id: &str,
+        dst_parent_dir: &str,
+    ) -> Result<()> {
+        self.transfer(
+            "/api/v2.1/repos/sync-batch-copy-item/",
+            src_repo_id,
+            src_parent_dir,
+            names,
+            dst_repo_id,
+            dst_parent_dir,
+        )
+    }
+
+    fn transfer(
+        &self,
+        endpoint: &str,
+        src_repo_id: &str,
+        src_parent_dir: &str,
+        names: &[&str],
+        dst_repo_id: &str,
+        dst_parent_dir: &str,
+    ) -> Result<()> {
+        let src_parent_dir = normalize_path(src_parent_dir);
+        let dst_parent_dir = normalize_path(dst_parent_dir);
+        let form = BatchTransfer {
+            src_repo_id,
+            src_parent_dir: &src_parent_dir,
+            src_dirents: names,
+            dst_repo_id,
+            dst_parent_dir: &dst_parent_dir,
+        };
+        self.send(endpoint, |client, url| client.post(url).json(&form))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+use crate::error::Result;
+use rand::Rng;
+use reqwest::{
+    blocking::{RequestBuilder, Response},
+    header::RETRY_AFTER,
+    StatusCode,
+};
+use std::{thread, time::Duration};
+
+/// How often and how patiently requests failing with transient errors are
+/// repeated: broken connections, timeouts, 5xx responses and 429 Too Many
+/// Requests.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt; 0 disables retrying.
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for every further one.
+    pub base_delay: Duration,
+    /// Upper bound of the delay between two attempts.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_retries: 0,
+            ..RetryPolicy::default()
+        }
+    }
+
+    /// The delay before retry `retry` (counting from 0): exponential, with
+    /// jitter so that clients failing together do not retry together.
+    fn backoff(&self, retry: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .checked_mul(1 << retry.min(16))
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay));
+        delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+
+    /// Sends the request built by `request` and retries on transient
+    /// failures. The request is built anew for every attempt since bodies
+    /// such as multipart forms cannot be cloned.
+    ///
+    /// Must not be used for requests that may not be repeated, like the
+    /// password form: every attempt counts toward Seafile's login lockout.
+    pub(crate) fn send(
+        &self,
+        mut request: impl FnMut() -> Result<RequestBuilder>,
+    ) -> Result<Response> {
+        let mut retry = 0;
+        loop {
+            let resp = match request()?.send() {
+                Ok(resp) => resp,
+                Err(e) if retry < self.max_retries && is_transient(&e) => {
+                    thread::sleep(self.backoff(retry));
+                    retry += 1;
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let status = resp.status();
+            if retry >= self.max_retries
+                || !(status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS)
+            {
+                return Ok(resp);
+            }
+            let delay = retry_after(&resp)
+                .map_or_else(|| self.backoff(retry), |delay| delay.min(self.max_delay));
+            thread::sleep(delay);
+            retry += 1;
+        }
+    }
+}
+
+/// Whether a request failed on the way rather than being malformed.
+fn is_transient(e: &reqwest::Error) -> bool {
+    e.is_connect() || e.is_timeout() || e.is_request() || e.is_body()
+}
+
+/// The delay asked for by a `Retry-After: <seconds>` header.
+fn retry_after(resp: &Response) -> Option<Duration> {
+    resp.headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
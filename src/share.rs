@@ -0,0 +1,193 @@
+use crate::{
+    account::Account,
+    error::{check_response, Result},
+    link::{Link, LinkKind},
+    repo::normalize_path,
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+/// Where the API manages download links.
+const SHARE_LINKS: &str = "/api/v2.1/share-links/";
+/// Where the API manages upload links.
+const UPLOAD_LINKS: &str = "/api/v2.1/upload-links/";
+
+/// What visitors of a download link may do besides viewing.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct LinkPermissions {
+    #[serde(default)]
+    pub can_edit: bool,
+    #[serde(default = "default_true")]
+    pub can_download: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for LinkPermissions {
+    fn default() -> Self {
+        LinkPermissions {
+            can_edit: false,
+            can_download: true,
+        }
+    }
+}
+
+/// A share link as the account's link management API describes it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LinkInfo {
+    pub token: String,
+    /// The URL to hand out.
+    pub link: String,
+    pub repo_id: String,
+    #[serde(default)]
+    pub repo_name: String,
+    pub path: String,
+    #[serde(default)]
+    pub ctime: String,
+    /// When the link expires, if it does.
+    #[serde(default)]
+    pub expire_date: Option<String>,
+    #[serde(default)]
+    pub is_expired: bool,
+    /// How often the link was opened.
+    #[serde(default)]
+    pub view_cnt: u64,
+    /// Only download links have permissions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permissions: Option<LinkPermissions>,
+    /// Recent servers tell the owner the password of a link; it is never
+    /// serialized.
+    #[serde(default, skip_serializing)]
+    pub password: Option<String>,
+}
+
+impl LinkInfo {
+    /// The kind of the link, taken from its URL.
+    pub fn kind(&self) -> Option<LinkKind> {
+        Link::parse(
+            &self.link,
+            &[LinkKind::Upload, LinkKind::Dir, LinkKind::File],
+        )
+        .ok()
+        .map(|link| link.kind)
+    }
+
+    pub fn is_upload(&self) -> bool {
+        self.kind() == Some(LinkKind::Upload)
+    }
+
+    /// Whether visitors need a password, as far as the server tells.
+    pub fn is_protected(&self) -> bool {
+        self.password
+            .as_deref()
+            .is_some_and(|password| !password.is_empty())
+    }
+}
+
+/// How a new share link is protected.
+#[derive(Clone, Debug, Default)]
+pub struct LinkOptions {
+    pub password: Option<String>,
+    /// Days until the link expires; it never does if not set.
+    pub expire_days: Option<u32>,
+    /// Ignored for upload links.
+    pub permissions: Option<LinkPermissions>,
+}
+
+#[derive(Serialize)]
+struct CreateLink<'a> {
+    repo_id: &'a str,
+    path: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    password: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expire_days: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    permissions: Option<LinkPermissions>,
+}
+
+impl<'a> CreateLink<'a> {
+    fn new(repo_id: &'a str, path: &'a str, options: &'a LinkOptions) -> Self {
+        CreateLink {
+            repo_id,
+            path,
+            password: options.password.as_deref(),
+            expire_days: options.expire_days,
+            permissions: options.permissions,
+        }
+    }
+}
+
+impl Account {
+    /// Creates a download link for file or directory `path` of library
+    /// `repo_id`.
+    pub fn create_download_link(
+        &self,
+        repo_id: &str,
+        path: &str,
+        options: &LinkOptions,
+    ) -> Result<LinkInfo> {
+        let path = normalize_path(path);
+        let form = CreateLink::new(repo_id, &path, options);
+        Ok(self
+            .send(SHARE_LINKS, |client, url| client.post(url).json(&form))?
+            .json()?)
+    }
+
+    /// Creates an upload link for directory `path` of library `repo_id`.
+    pub fn create_upload_link(
+        &self,
+        repo_id: &str,
+        path: &str,
+        options: &LinkOptions,
+    ) -> Result<LinkInfo> {
+        let path = normalize_path(path);
+        let form = CreateLink {
+            permissions: None,
+            ..CreateLink::new(repo_id, &path, options)
+        };
+        Ok(self
+            .send(UPLOAD_LINKS, |client, url| client.post(url).json(&form))?
+            .json()?)
+    }
+
+    /// Lists the download links of the account, then its upload links.
+    pub fn list_links(&self) -> Result<Vec<LinkInfo>> {
+        let mut links: Vec<LinkInfo> = self
+            .send(SHARE_LINKS, |client, url| client.get(url))?
+            .json()?;
+        let upload_links: Vec<LinkInfo> = self
+            .send(UPLOAD_LINKS, |client, url| client.get(url))?
+            .json()?;
+        links.extend(upload_links);
+        Ok(links)
+    }
+
+    /// Finds the link of `token` among the download links, then the upload
+    /// links, and returns the API path it is managed under.
+    fn find_link(&self, token: &str) -> Result<(String, LinkInfo)> {
+        let path = format!("{}{}/", SHARE_LINKS, token);
+        let resp = self.send_unchecked(&path, |client, url| client.get(url))?;
+        if resp.status() != StatusCode::NOT_FOUND {
+            return Ok((path, check_response(resp)?.json()?));
+        }
+        let path = format!("{}{}/", UPLOAD_LINKS, token);
+        let info = self.send(&path, |client, url| client.get(url))?.json()?;
+        Ok((path, info))
+    }
+
+    /// Describes the download or upload link of `token`.
+    pub fn link_info(&self, token: &str) -> Result<LinkInfo> {
+        Ok(self.find_link(token)?.1)
+    }
+
+    /// Revokes the download or upload link of `token` and returns what it
+    /// was.
+    pub fn delete_link(&self, token: &str) -> Result<LinkInfo> {
+        let (path, info) = self.find_link(token)?;
+        self.send(&path, |client, url| client.delete(url))?;
+        Ok(info)
+    }
+}
@@ -0,0 +1,745 @@
+use crate::{
+    account::Account,
+    error::{Error, Result},
+    progress::{progress_reader, Progress},
+    repo::{normalize_path, Dirent},
+    upload::{FileUploadResp, OnConflict, UploadItem, UploadOptions},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::{self, ErrorKind, Read},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::UNIX_EPOCH,
+};
+
+/// How a sync tells whether a local file differs from its remote copy.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compare {
+    /// Files differ in size, or the local one was modified after the remote
+    /// one was uploaded.
+    SizeMtime,
+    /// Files differ in size or SHA-1 digest. Remote files of the same size
+    /// as the local ones are downloaded to be hashed.
+    Checksum,
+}
+
+/// How a local directory is mirrored to a remote one.
+#[derive(Clone, Copy, Debug)]
+pub struct SyncOptions {
+    pub compare: Compare,
+    /// Deletes remote files and directories that do not exist locally.
+    pub delete: bool,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        SyncOptions {
+            compare: Compare::SizeMtime,
+            delete: false,
+        }
+    }
+}
+
+/// A step of a sync, on the absolute path `remote` in the library. Only
+/// two-way syncs download, delete local files and resolve conflicts.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum SyncAction {
+    Mkdir {
+        remote: String,
+    },
+    Upload {
+        local: PathBuf,
+        remote: String,
+        size: u64,
+        /// Whether a remote file is replaced.
+        changed: bool,
+    },
+    Delete {
+        remote: String,
+    },
+    Download {
+        remote: String,
+        local: PathBuf,
+        size: u64,
+        /// The version downloaded.
+        id: String,
+    },
+    DeleteLocal {
+        remote: String,
+        local: PathBuf,
+    },
+    /// The file changed on both sides: the local version is renamed to
+    /// `copy` and uploaded as such, then the remote version is downloaded.
+    Conflict {
+        remote: String,
+        local: PathBuf,
+        copy: PathBuf,
+        id: String,
+    },
+}
+
+impl SyncAction {
+    pub fn remote(&self) -> &str {
+        match self {
+            SyncAction::Mkdir { remote }
+            | SyncAction::Upload { remote, .. }
+            | SyncAction::Delete { remote }
+            | SyncAction::Download { remote, .. }
+            | SyncAction::DeleteLocal { remote, .. }
+            | SyncAction::Conflict { remote, .. } => remote,
+        }
+    }
+}
+
+/// A file as it was on both sides after it was last synced.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FileState {
+    /// Size and modification time of the local file.
+    pub size: u64,
+    pub mtime: i64,
+    /// The remote version, which changes with the content.
+    pub id: String,
+}
+
+impl FileState {
+    pub fn new(metadata: &fs::Metadata, id: String) -> Self {
+        FileState {
+            size: metadata.len(),
+            mtime: local_mtime(metadata),
+            id,
+        }
+    }
+
+    /// Whether the local file is as it was.
+    pub fn matches(&self, metadata: &fs::Metadata) -> bool {
+        metadata.len() == self.size && local_mtime(metadata) == self.mtime
+    }
+}
+
+/// The state database of a two-way sync: the files as they were after the
+/// last sync, by path relative to the synced directories. Files changed on
+/// both sides since then are in conflict.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct SyncState {
+    pub files: BTreeMap<String, FileState>,
+}
+
+impl SyncState {
+    /// Loads the state saved at `path`; the state of a first sync is empty.
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .map_err(|e| Error::Local(format!("{}: {}", path.display(), e))),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(SyncState::default()),
+            Err(e) => Err(Error::Local(format!("{}: {}", path.display(), e))),
+        }
+    }
+
+    /// Saves the state to `path`, replacing the previous one at once so that
+    /// an interrupted save leaves it intact.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let local_error = |e: io::Error| Error::Local(format!("{}: {}", path.display(), e));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(local_error)?;
+        }
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(self).unwrap()).map_err(local_error)?;
+        fs::rename(&tmp, path).map_err(local_error)
+    }
+
+    fn record(&mut self, key: String, local: &Path, id: String) -> Result<()> {
+        let metadata =
+            fs::metadata(local).map_err(|e| Error::Local(format!("{}: {}", local.display(), e)))?;
+        self.files.insert(key, FileState::new(&metadata, id));
+        Ok(())
+    }
+}
+
+/// Seconds since the epoch of the last modification of a local file.
+pub(crate) fn local_mtime(metadata: &fs::Metadata) -> i64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |mtime| mtime.as_secs() as i64)
+}
+
+/// The SHA-1 digest of everything `reader` yields, in hex.
+pub(crate) fn digest(mut reader: impl Read) -> io::Result<String> {
+    let mut sha1 = sha1_smol::Sha1::new();
+    let mut buf = vec![0; 64 << 10];
+    loop {
+        match reader.read(&mut buf)? {
+            0 => return Ok(sha1.digest().to_string()),
+            n => sha1.update(&buf[..n]),
+        }
+    }
+}
+
+fn join_remote(dir: &str, name: &str) -> String {
+    normalize_path(&format!("{}/{}", dir, name))
+}
+
+/// Splits a remote path into its parent directory and name.
+fn split_remote(remote: &str) -> (String, &str) {
+    match remote.rfind('/') {
+        Some(slash) => (normalize_path(&remote[..slash]), &remote[slash + 1..]),
+        None => ("/".to_string(), remote),
+    }
+}
+
+/// The path of `remote` relative to the synced directory `dir`, which keys
+/// the state database.
+fn state_key(dir: &str, remote: &str) -> String {
+    remote
+        .strip_prefix(dir)
+        .unwrap_or(remote)
+        .trim_start_matches('/')
+        .to_string()
+}
+
+/// A name for the local version of a file in conflict, `<key>` turned into
+/// `a (conflicted copy).txt`, `a (conflicted copy 2).txt`, ... whichever
+/// is not `taken`.
+fn conflict_copy(key: &str, taken: impl Fn(&str) -> bool) -> String {
+    let (parent, name) = match key.rfind('/') {
+        Some(slash) => (&key[..=slash], &key[slash + 1..]),
+        None => ("", key),
+    };
+    let (stem, ext) = match name.rfind('.') {
+        Some(dot) if dot > 0 => (&name[..dot], &name[dot..]),
+        _ => (name, ""),
+    };
+    (1..)
+        .map(|n| match n {
+            1 => format!("{}{} (conflicted copy){}", parent, stem, ext),
+            n => format!("{}{} (conflicted copy {}){}", parent, stem, n, ext),
+        })
+        .find(|copy| !taken(copy))
+        .unwrap()
+}
+
+/// Collects the files below local directory `dir` by path relative to the
+/// synced directory, `rel`.
+fn walk_local(
+    dir: &Path,
+    rel: &str,
+    files: &mut BTreeMap<String, (PathBuf, fs::Metadata)>,
+) -> Result<()> {
+    for (name, (path, metadata)) in read_local_dir(dir)? {
+        let key = match rel {
+            "" => name,
+            rel => format!("{}/{}", rel, name),
+        };
+        if metadata.is_dir() {
+            walk_local(&path, &key, files)?;
+        } else {
+            files.insert(key, (path, metadata));
+        }
+    }
+    Ok(())
+}
+
+/// The entries of local directory `dir` by name, following symbolic links
+/// and leaving out anything that is neither a file nor a directory.
+fn read_local_dir(dir: &Path) -> Result<BTreeMap<String, (PathBuf, fs::Metadata)>> {
+    let read_dir =
+        fs::read_dir(dir).map_err(|e| Error::Local(format!("{}: {}", dir.display(), e)))?;
+    let mut entries = BTreeMap::new();
+    for entry in read_dir {
+        let path = entry?.path();
+        let metadata =
+            fs::metadata(&path).map_err(|e| Error::Local(format!("{}: {}", path.display(), e)))?;
+        if metadata.is_file() || metadata.is_dir() {
+            let name = path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned();
+            entries.insert(name, (path, metadata));
+        }
+    }
+    Ok(entries)
+}
+
+impl Account {
+    /// Plans mirroring local directory `local` to directory `dir` of library
+    /// `repo_id`: the directories to create, the new and changed files to
+    /// upload and, with [`SyncOptions::delete`], what to delete. Nothing is
+    /// changed; see [`Account::apply_sync`].
+    pub fn plan_sync(
+        &self,
+        local: &Path,
+        repo_id: &str,
+        dir: &str,
+        options: &SyncOptions,
+    ) -> Result<Vec<SyncAction>> {
+        if !local.is_dir() {
+            return Err(Error::Usage(format!(
+                "{}: not a directory",
+                local.display()
+            )));
+        }
+        let dir = normalize_path(dir);
+        let mut plan = Vec::new();
+        let remote = match self.stat(repo_id, &dir)? {
+            Some(dirent) if dirent.is_dir() => Some(self.list_dir(repo_id, &dir)?),
+            Some(_) => return Err(Error::Usage(format!("{}: not a directory", dir))),
+            None => {
+                plan.push(SyncAction::Mkdir {
+                    remote: dir.clone(),
+                });
+                None
+            }
+        };
+        self.plan_dir(local, repo_id, &dir, remote, options, &mut plan)?;
+        Ok(plan)
+    }
+
+    /// Plans the sync of local directory `local` to remote directory `dir`
+    /// listed as `remote`, or missing if `None`.
+    fn plan_dir(
+        &self,
+        local: &Path,
+        repo_id: &str,
+        dir: &str,
+        remote: Option<Vec<Dirent>>,
+        options: &SyncOptions,
+        plan: &mut Vec<SyncAction>,
+    ) -> Result<()> {
+        let mut remote: BTreeMap<String, Dirent> = remote
+            .unwrap_or_default()
+            .into_iter()
+            .map(|dirent| (dirent.name.clone(), dirent))
+            .collect();
+        for (name, (path, metadata)) in read_local_dir(local)? {
+            let remote_path = join_remote(dir, &name);
+            let dirent = remote.remove(&name);
+            // An entry of the other type is in the way.
+            let dirent = match dirent {
+                Some(dirent) if dirent.is_dir() != metadata.is_dir() => {
+                    if !options.delete {
+                        return Err(Error::Conflict(format!(
+                            "{} is a {} remotely but not locally, use --delete to replace it",
+                            remote_path,
+                            if dirent.is_dir() { "directory" } else { "file" }
+                        )));
+                    }
+                    plan.push(SyncAction::Delete {
+                        remote: remote_path.clone(),
+                    });
+                    None
+                }
+                dirent => dirent,
+            };
+            if metadata.is_dir() {
+                let listing = match dirent {
+                    Some(_) => Some(self.list_dir(repo_id, &remote_path)?),
+                    None => {
+                        plan.push(SyncAction::Mkdir {
+                            remote: remote_path.clone(),
+                        });
+                        None
+                    }
+                };
+                self.plan_dir(&path, repo_id, &remote_path, listing, options, plan)?;
+                continue;
+            }
+            let changed = match &dirent {
+                None => None,
+                Some(dirent) => {
+                    Some(self.differs(&path, &metadata, repo_id, &remote_path, dirent, options)?)
+                }
+            };
+            if changed != Some(false) {
+                plan.push(SyncAction::Upload {
+                    local: path,
+                    remote: remote_path,
+                    size: metadata.len(),
+                    changed: changed.is_some(),
+                });
+            }
+        }
+        if options.delete {
+            for name in remote.keys() {
+                plan.push(SyncAction::Delete {
+                    remote: join_remote(dir, name),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn differs(
+        &self,
+        path: &Path,
+        metadata: &fs::Metadata,
+        repo_id: &str,
+        remote_path: &str,
+        dirent: &Dirent,
+        options: &SyncOptions,
+    ) -> Result<bool> {
+        if metadata.len() != dirent.size {
+            return Ok(true);
+        }
+        Ok(match options.compare {
+            Compare::SizeMtime => local_mtime(metadata) > dirent.mtime,
+            Compare::Checksum => {
+                let file = fs::File::open(path)
+                    .map_err(|e| Error::Local(format!("{}: {}", path.display(), e)))?;
+                digest(file)? != digest(self.read_file(repo_id, remote_path)?)?
+            }
+        })
+    }
+
+    /// Carries out one step of a plan from [`Account::plan_sync`], uploading
+    /// in chunks of `chunk_size`.
+    pub fn apply_sync(
+        &self,
+        repo_id: &str,
+        action: &SyncAction,
+        chunk_size: u64,
+        progress: &Arc<dyn Progress>,
+    ) -> Result<()> {
+        self.apply(repo_id, None, action, chunk_size, progress)?;
+        Ok(())
+    }
+
+    /// Carries out `action` and returns what the server stored for its
+    /// upload, if any. Uploads are posted to `root` if given, which has the
+    /// file server create the directories between it and the file, and else
+    /// to the directory of the file.
+    fn apply(
+        &self,
+        repo_id: &str,
+        root: Option<&str>,
+        action: &SyncAction,
+        chunk_size: u64,
+        progress: &Arc<dyn Progress>,
+    ) -> Result<Option<FileUploadResp>> {
+        let remote = normalize_path(action.remote());
+        let (parent, name) = split_remote(&remote);
+        let upload_dir = root.unwrap_or(&parent);
+        let relative_path = state_key(upload_dir, &parent);
+        let upload = |local: &Path| {
+            self.upload_replacing(
+                repo_id,
+                upload_dir,
+                &relative_path,
+                local,
+                chunk_size,
+                progress,
+            )
+        };
+        match action {
+            SyncAction::Mkdir { .. } => self.mkdir(repo_id, &remote, true)?,
+            SyncAction::Delete { .. } => self.delete(repo_id, &parent, &[name])?,
+            SyncAction::Upload { local, .. } => return Ok(Some(upload(local)?)),
+            SyncAction::Download { local, .. } => {
+                self.download_to(repo_id, &remote, local, progress)?;
+            }
+            SyncAction::DeleteLocal { local, .. } => remove_local(local)?,
+            SyncAction::Conflict { local, copy, .. } => {
+                fs::rename(local, copy)
+                    .map_err(|e| Error::Local(format!("{}: {}", local.display(), e)))?;
+                let resp = upload(copy)?;
+                self.download_to(repo_id, &remote, local, progress)?;
+                return Ok(Some(resp));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Uploads local file `local` into `relative_path` below remote
+    /// directory `dir`, replacing the file of the same name.
+    fn upload_replacing(
+        &self,
+        repo_id: &str,
+        dir: &str,
+        relative_path: &str,
+        local: &Path,
+        chunk_size: u64,
+        progress: &Arc<dyn Progress>,
+    ) -> Result<FileUploadResp> {
+        let item = UploadItem {
+            path: local.to_path_buf(),
+            relative_path: relative_path.to_string(),
+        };
+        let options = UploadOptions {
+            chunk_size,
+            on_conflict: OnConflict::Overwrite,
+        };
+        let resp = self.upload_item(repo_id, dir, &item, &options, progress)?;
+        Ok(resp.expect("uploads replacing files are never skipped"))
+    }
+
+    /// Saves remote file `remote` as `local`, creating its parents. The file
+    /// is written next to `local` first so that it is never seen half done.
+    fn download_to(
+        &self,
+        repo_id: &str,
+        remote: &str,
+        local: &Path,
+        progress: &Arc<dyn Progress>,
+    ) -> Result<u64> {
+        let local_error = |e: io::Error| Error::Local(format!("{}: {}", local.display(), e));
+        if let Some(parent) = local.parent() {
+            fs::create_dir_all(parent).map_err(local_error)?;
+        }
+        let mut part = local.as_os_str().to_owned();
+        part.push(".seaf-web-part");
+        let part = PathBuf::from(part);
+        let mut reader = progress_reader(self.read_file(repo_id, remote)?, progress);
+        let mut file = fs::File::create(&part).map_err(local_error)?;
+        let size = io::copy(&mut reader, &mut file)?;
+        fs::rename(&part, local).map_err(local_error)?;
+        Ok(size)
+    }
+
+    /// Collects the files below remote directory `dir` by path relative to
+    /// the synced directory, `rel`.
+    fn walk_remote(
+        &self,
+        repo_id: &str,
+        dir: &str,
+        rel: &str,
+        files: &mut BTreeMap<String, Dirent>,
+    ) -> Result<()> {
+        for dirent in self.list_dir(repo_id, dir)? {
+            let key = match rel {
+                "" => dirent.name.clone(),
+                rel => format!("{}/{}", rel, dirent.name),
+            };
+            if dirent.is_dir() {
+                self.walk_remote(repo_id, &join_remote(dir, &dirent.name), &key, files)?;
+            } else {
+                files.insert(key, dirent);
+            }
+        }
+        Ok(())
+    }
+
+    /// Plans a two-way sync of local directory `local` and directory `dir`
+    /// of library `repo_id` against `state`, the state database of their
+    /// last sync.
+    ///
+    /// Files changed on one side since the last sync are copied to the
+    /// other, including deletions; files changed on both sides are in
+    /// conflict, unless their content is the same. Files that are already in
+    /// sync are recorded in `state` right away, the others once their
+    /// action is applied with [`Account::apply_two_way`]. Empty directories
+    /// are not synced; the remote directories of new files are created as
+    /// they are uploaded, only a missing `dir` is planned as a
+    /// [`SyncAction::Mkdir`].
+    pub fn plan_two_way(
+        &self,
+        local: &Path,
+        repo_id: &str,
+        dir: &str,
+        state: &mut SyncState,
+    ) -> Result<Vec<SyncAction>> {
+        if !local.is_dir() {
+            return Err(Error::Usage(format!(
+                "{}: not a directory",
+                local.display()
+            )));
+        }
+        let dir = normalize_path(dir);
+        let mut locals = BTreeMap::new();
+        walk_local(local, "", &mut locals)?;
+        let mut remotes = BTreeMap::new();
+        let mut plan = Vec::new();
+        match self.stat(repo_id, &dir)? {
+            Some(dirent) if dirent.is_dir() => self.walk_remote(repo_id, &dir, "", &mut remotes)?,
+            Some(_) => return Err(Error::Usage(format!("{}: not a directory", dir))),
+            // Uploads are posted to it.
+            None => plan.push(SyncAction::Mkdir {
+                remote: dir.clone(),
+            }),
+        }
+        let keys: BTreeSet<String> = locals
+            .keys()
+            .chain(remotes.keys())
+            .chain(state.files.keys())
+            .cloned()
+            .collect();
+        for key in keys {
+            let local_file = locals.get(&key);
+            let remote_file = remotes.get(&key);
+            let last = state.files.get(&key);
+            let local_changed = match (local_file, last) {
+                (Some((_, metadata)), Some(last)) => !last.matches(metadata),
+                (None, None) => false,
+                _ => true,
+            };
+            let remote_changed = match (remote_file, last) {
+                (Some(dirent), Some(last)) => dirent.id != last.id,
+                (None, None) => false,
+                _ => true,
+            };
+            let remote = join_remote(&dir, &key);
+            let upload = |(path, metadata): &(PathBuf, fs::Metadata)| SyncAction::Upload {
+                local: path.clone(),
+                remote: remote.clone(),
+                size: metadata.len(),
+                changed: remote_file.is_some(),
+            };
+            let download = |dirent: &Dirent| SyncAction::Download {
+                remote: remote.clone(),
+                local: local.join(&key),
+                size: dirent.size,
+                id: dirent.id.clone(),
+            };
+            let action = match (local_file, remote_file) {
+                _ if !local_changed && !remote_changed => None,
+                // Deleted on both sides.
+                (None, None) => {
+                    state.files.remove(&key);
+                    None
+                }
+                (Some(local_file), None) if local_changed => Some(upload(local_file)),
+                (None, Some(dirent)) if remote_changed => Some(download(dirent)),
+                (Some(_), None) => Some(SyncAction::DeleteLocal {
+                    remote: remote.clone(),
+                    local: local.join(&key),
+                }),
+                (None, Some(_)) => Some(SyncAction::Delete {
+                    remote: remote.clone(),
+                }),
+                (Some(local_file), Some(_)) if !remote_changed => Some(upload(local_file)),
+                (Some(_), Some(dirent)) if !local_changed => Some(download(dirent)),
+                (Some((path, metadata)), Some(dirent)) => {
+                    if self.same_content(path, metadata, repo_id, &remote, dirent)? {
+                        state.record(key.clone(), path, dirent.id.clone())?;
+                        None
+                    } else {
+                        let copy = conflict_copy(&key, |copy| {
+                            locals.contains_key(copy)
+                                || remotes.contains_key(copy)
+                                || local.join(copy).exists()
+                        });
+                        Some(SyncAction::Conflict {
+                            remote: remote.clone(),
+                            local: path.clone(),
+                            copy: local.join(copy),
+                            id: dirent.id.clone(),
+                        })
+                    }
+                }
+            };
+            plan.extend(action);
+        }
+        Ok(plan)
+    }
+
+    fn same_content(
+        &self,
+        path: &Path,
+        metadata: &fs::Metadata,
+        repo_id: &str,
+        remote: &str,
+        dirent: &Dirent,
+    ) -> Result<bool> {
+        Ok(!self.differs(
+            path,
+            metadata,
+            repo_id,
+            remote,
+            dirent,
+            &SyncOptions {
+                compare: Compare::Checksum,
+                delete: false,
+            },
+        )?)
+    }
+
+    /// Carries out one step of a plan from [`Account::plan_two_way`] and
+    /// records its outcome in `state`.
+    pub fn apply_two_way(
+        &self,
+        repo_id: &str,
+        dir: &str,
+        action: &SyncAction,
+        chunk_size: u64,
+        progress: &Arc<dyn Progress>,
+        state: &mut SyncState,
+    ) -> Result<()> {
+        let dir = normalize_path(dir);
+        let remote = normalize_path(action.remote());
+        let key = state_key(&dir, &remote);
+        let resp = self.apply(repo_id, Some(&dir), action, chunk_size, progress)?;
+        match (action, resp) {
+            (SyncAction::Upload { local, .. }, Some(resp)) => state.record(key, local, resp.id)?,
+            (SyncAction::Download { local, id, .. }, _) => state.record(key, local, id.clone())?,
+            (
+                SyncAction::Conflict {
+                    local, copy, id, ..
+                },
+                Some(resp),
+            ) => {
+                let copy_name = copy.file_name().unwrap_or_default().to_string_lossy();
+                let copy_key = state_key(
+                    &dir,
+                    &join_remote(split_remote(&remote).0.as_str(), &copy_name),
+                );
+                state.record(copy_key, copy, resp.id)?;
+                state.record(key, local, id.clone())?;
+            }
+            (SyncAction::Delete { .. }, _) | (SyncAction::DeleteLocal { .. }, _) => {
+                state.files.remove(&key);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Deletes a local file; one already gone is fine.
+fn remove_local(local: &Path) -> Result<()> {
+    match fs::remove_file(local) {
+        Err(e) if e.kind() != ErrorKind::NotFound => {
+            Err(Error::Local(format!("{}: {}", local.display(), e)))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conflict_copies_keep_the_extension() {
+        let none = |_: &str| false;
+        assert_eq!(conflict_copy("a.txt", none), "a (conflicted copy).txt");
+        assert_eq!(
+            conflict_copy("sub/a.tar.gz", none),
+            "sub/a.tar (conflicted copy).gz"
+        );
+        assert_eq!(conflict_copy("notes", none), "notes (conflicted copy)");
+        assert_eq!(conflict_copy(".bashrc", none), ".bashrc (conflicted copy)");
+    }
+
+    #[test]
+    fn conflict_copies_are_numbered_past_taken_names() {
+        let taken = [
+            "sub/a (conflicted copy).txt",
+            "sub/a (conflicted copy 2).txt",
+        ];
+        assert_eq!(
+            conflict_copy("sub/a.txt", |copy| taken.contains(&copy)),
+            "sub/a (conflicted copy 3).txt"
+        );
+    }
+
+    #[test]
+    fn state_keys_are_relative_to_the_synced_directory() {
+        assert_eq!(state_key("/synced", "/synced/sub/a.txt"), "sub/a.txt");
+        assert_eq!(state_key("/", "/sub/a.txt"), "sub/a.txt");
+        assert_eq!(state_key("/synced", "/synced"), "");
+    }
+}
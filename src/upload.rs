@@ -0,0 +1,790 @@
+use crate::{
+    account::Account,
+    error::{check_response, Error, Result},
+    link::{
+        client, find_page_error, open_page, post_password, server_major_version, LinkKind, LinkPage,
+    },
+    progress::{progress_part, NoProgress, Progress},
+    repo::normalize_path,
+    retry::RetryPolicy,
+};
+use lazy_static::lazy_static;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use regex::Regex;
+use reqwest::blocking::{multipart::Form, Client};
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A file as stored by the server after an upload.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FileUploadResp {
+    pub name: String,
+    pub id: String,
+    pub size: usize,
+}
+
+/// A local file to upload and the directory, relative to the root of the
+/// upload link, it goes into.
+#[derive(Clone, Debug)]
+pub struct UploadItem {
+    pub path: PathBuf,
+    pub relative_path: String,
+}
+
+impl UploadItem {
+    /// Where the file ends up, relative to the root of the upload link.
+    pub fn remote_path(&self, name: &str) -> String {
+        remote_path(&self.relative_path, name)
+    }
+}
+
+fn remote_path(relative_path: &str, name: &str) -> String {
+    match relative_path {
+        "" => format!("/{}", name),
+        dir => format!("/{}/{}", dir, name),
+    }
+}
+
+/// Turns a remote directory such as `/reports/2021/` into the
+/// `relative_path` form the upload form expects, `reports/2021`.
+pub fn relative_dir(dir: &str) -> Result<String> {
+    let mut components = Vec::new();
+    for component in dir.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                return Err(Error::Usage(format!(
+                    "{}: remote directory must not contain `..`",
+                    dir
+                )))
+            }
+            component => components.push(component),
+        }
+    }
+    Ok(components.join("/"))
+}
+
+/// Collects the files to upload for `path`, recursing into directories so
+/// that their structure is recreated under `relative_path`.
+pub fn collect_upload_items(
+    path: &Path,
+    relative_path: &str,
+    items: &mut Vec<UploadItem>,
+) -> Result<()> {
+    if !path.is_dir() {
+        items.push(UploadItem {
+            path: path.to_path_buf(),
+            relative_path: relative_path.to_string(),
+        });
+        return Ok(());
+    }
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        // `.` and `..` are named after the directory they resolve to.
+        None => path
+            .canonicalize()?
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    };
+    let relative_path = match (relative_path, name.as_str()) {
+        (parent, "") => parent.to_string(),
+        ("", name) => name.to_string(),
+        (parent, name) => format!("{}/{}", parent, name),
+    };
+    let mut entries = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    for entry in entries {
+        collect_upload_items(&entry, &relative_path, items)?;
+    }
+    Ok(())
+}
+
+/// What to do when a file of the same name already exists remotely.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OnConflict {
+    /// Let the server store the upload under a new name, e.g. `a (1).txt`.
+    Rename,
+    /// Replace the existing file.
+    Overwrite,
+    /// Keep the existing file and do not upload.
+    Skip,
+    /// Fail with [`Error::Conflict`].
+    Fail,
+}
+
+/// How files are uploaded.
+#[derive(Clone, Debug)]
+pub struct UploadOptions {
+    /// Files larger than this are uploaded in resumable chunks of this size.
+    pub chunk_size: u64,
+    pub on_conflict: OnConflict,
+}
+
+impl Default for UploadOptions {
+    fn default() -> Self {
+        UploadOptions {
+            chunk_size: 8 << 20,
+            on_conflict: OnConflict::Rename,
+        }
+    }
+}
+
+/// The generations of upload-link pages, which name the library of the link
+/// and hand out upload URLs differently.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PageLayout {
+    /// The jQuery uploader of Seafile 8 and earlier, whose inline script asks
+    /// `/ajax/u/d/<token>/upload/?r=<repo_id>` for upload URLs.
+    Script,
+    /// The React page of Seafile 9 and later, which describes the link in
+    /// `window.shared.pageOptions` and asks
+    /// `/api/v2.1/upload-links/<token>/upload/` for upload URLs.
+    React,
+}
+
+impl PageLayout {
+    /// The layout served by Seafile `major_version`.
+    fn of_version(major_version: u32) -> Self {
+        if major_version >= 9 {
+            PageLayout::React
+        } else {
+            PageLayout::Script
+        }
+    }
+}
+
+/// Finds the library of the link in its unlocked page, which is of `layout`
+/// if the server version is known and else of whichever layout matches.
+fn extract_repo_id(document: &Html, layout: Option<PageLayout>) -> Result<(PageLayout, String)> {
+    lazy_static! {
+        static ref SCRIPT_RE: Regex = Regex::new(concat!(
+            r"'/ajax/u/d/[0-9a-f]{20}/upload/\?r=",
+            r"([0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12})'"
+        ))
+        .unwrap();
+        static ref REACT_RE: Regex = Regex::new(concat!(
+            r#"(?s)window\.shared\s*=.*pageOptions\s*:.*repoID\s*:\s*['"]"#,
+            r"([0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12})"
+        ))
+        .unwrap();
+    }
+    let layouts = match &layout {
+        Some(layout) => std::slice::from_ref(layout),
+        None => &[PageLayout::React, PageLayout::Script],
+    };
+    for script in document.select(&Selector::parse("script").unwrap()) {
+        for text in script.text() {
+            for &layout in layouts {
+                let re: &Regex = match layout {
+                    PageLayout::Script => &SCRIPT_RE,
+                    PageLayout::React => &REACT_RE,
+                };
+                if let Some(caps) = re.captures(text) {
+                    return Ok((layout, caps[1].to_string()));
+                }
+            }
+        }
+    }
+    Err(match find_page_error(document) {
+        Some(msg) => Error::InvalidLink(msg),
+        None => Error::Layout(
+            match layout {
+                Some(PageLayout::Script) => "no upload script found",
+                Some(PageLayout::React) => "no page options found",
+                None => "neither an upload script nor page options found",
+            }
+            .into(),
+        ),
+    })
+}
+
+#[derive(Deserialize)]
+struct UploadUrl {
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct UploadLinkUrl {
+    upload_link: String,
+}
+
+#[derive(Deserialize)]
+struct UploadedBytes {
+    #[serde(rename = "uploadedBytes")]
+    uploaded_bytes: u64,
+}
+
+/// Characters `encodeURIComponent` leaves alone, which is how Seafile's web
+/// uploader encodes the file name in `Content-Disposition`.
+const URI_COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'!')
+    .remove(b'~')
+    .remove(b'*')
+    .remove(b'\'')
+    .remove(b'(')
+    .remove(b')');
+
+fn local_file_name(file_path: &Path) -> Result<String> {
+    Ok(file_path
+        .file_name()
+        .ok_or_else(|| Error::Usage(format!("{}: no file name", file_path.display())))?
+        .to_string_lossy()
+        .into_owned())
+}
+
+/// The fields of the upload form besides the file itself.
+fn upload_form(parent_dir: &str, relative_path: &str, replace: bool) -> Form {
+    let form = Form::new()
+        .text("parent_dir", parent_dir.to_string())
+        .text("relative_path", relative_path.to_string());
+    if replace {
+        form.text("replace", "1")
+    } else {
+        form
+    }
+}
+
+/// Refuses the policies upload links cannot honour and tells whether to
+/// ask the server to replace existing files.
+fn check_on_conflict(options: &UploadOptions) -> Result<bool> {
+    match options.on_conflict {
+        OnConflict::Skip => Err(Error::Usage(
+            "upload links cannot check for existing files, so they cannot skip them".into(),
+        )),
+        on_conflict => Ok(on_conflict == OnConflict::Overwrite),
+    }
+}
+
+/// Fails under [`OnConflict::Fail`] if the server stored the upload under
+/// another name than `local_name`, because a file of that name existed. The
+/// error names the stored copy, which is left for the user to deal with.
+fn check_stored_name(
+    options: &UploadOptions,
+    relative_path: &str,
+    local_name: &str,
+    resp: FileUploadResp,
+) -> Result<FileUploadResp> {
+    if options.on_conflict == OnConflict::Fail && resp.name != local_name {
+        return Err(Error::Conflict(format!(
+            "{} already exists, the upload was stored as {} (id {})",
+            remote_path(relative_path, local_name),
+            remote_path(relative_path, &resp.name),
+            resp.id
+        )));
+    }
+    Ok(resp)
+}
+
+/// A client for an upload link, `<server>/u/d/<token>/`.
+///
+/// Opening the link fetches its page; links protected by a password have to
+/// be [authenticated](UploadLink::authenticate) once before uploading.
+pub struct UploadLink {
+    client: Client,
+    retry: RetryPolicy,
+    server: String,
+    token: String,
+    /// Known from the server version, or else once the page is unlocked.
+    layout: Option<PageLayout>,
+    repo_id: Option<String>,
+    csrfmiddlewaretoken: Option<String>,
+}
+
+impl UploadLink {
+    /// Opens upload link `token` on `server`, e.g.
+    /// `https://cloud.tsinghua.edu.cn`.
+    pub fn open(server: &str, token: &str) -> Result<Self> {
+        Self::open_with_client(client()?, RetryPolicy::default(), server, token)
+    }
+
+    /// Like [`UploadLink::open`], with a client and retry policy configured
+    /// by the caller. The client needs a cookie store to keep the session of
+    /// the link.
+    pub fn open_with_client(
+        client: Client,
+        retry: RetryPolicy,
+        server: &str,
+        token: &str,
+    ) -> Result<Self> {
+        let server = server.trim_end_matches('/').to_string();
+        let mut layout =
+            server_major_version(&client, &retry, &server)?.map(PageLayout::of_version);
+        let (repo_id, csrfmiddlewaretoken) =
+            match open_page(&client, &retry, &LinkKind::Upload.url(&server, token))? {
+                LinkPage::Open(document) => {
+                    let (page_layout, repo_id) = extract_repo_id(&document, layout)?;
+                    layout = Some(page_layout);
+                    (Some(repo_id), None)
+                }
+                LinkPage::Locked {
+                    csrfmiddlewaretoken,
+                } => (None, Some(csrfmiddlewaretoken)),
+            };
+        Ok(UploadLink {
+            client,
+            retry,
+            server,
+            token: token.to_string(),
+            layout,
+            repo_id,
+            csrfmiddlewaretoken,
+        })
+    }
+
+    pub fn server(&self) -> &str {
+        &self.server
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    pub fn needs_password(&self) -> bool {
+        self.repo_id.is_none()
+    }
+
+    /// The layout of the link page; `None` until a locked link is unlocked
+    /// if the server does not tell its version.
+    pub fn layout(&self) -> Option<PageLayout> {
+        self.layout
+    }
+
+    /// Unlocks a link protected by a password.
+    pub fn authenticate(&mut self, password: &str) -> Result<()> {
+        let csrfmiddlewaretoken = match &self.csrfmiddlewaretoken {
+            Some(csrfmiddlewaretoken) => csrfmiddlewaretoken,
+            None => return Ok(()),
+        };
+        let document = post_password(
+            &self.client,
+            &LinkKind::Upload.url(&self.server, &self.token),
+            &self.token,
+            csrfmiddlewaretoken,
+            password,
+        )?;
+        let (layout, repo_id) = extract_repo_id(&document, self.layout)?;
+        self.layout = Some(layout);
+        self.repo_id = Some(repo_id);
+        self.csrfmiddlewaretoken = None;
+        Ok(())
+    }
+
+    fn repo_id(&self) -> Result<&str> {
+        self.repo_id.as_deref().ok_or(Error::WrongPassword)
+    }
+
+    /// Asks for a fresh URL of the file server to post files to.
+    pub fn upload_url(&self) -> Result<String> {
+        let repo_id = self.repo_id()?;
+        match self.layout {
+            Some(PageLayout::React) => self.api_upload_url(),
+            _ => self.ajax_upload_url(repo_id),
+        }
+    }
+
+    /// Asks the endpoint of the jQuery uploader for an upload URL.
+    fn ajax_upload_url(&self, repo_id: &str) -> Result<String> {
+        let url = format!(
+            "{}/ajax/u/d/{}/upload/?r={}&_={}",
+            self.server,
+            self.token,
+            repo_id,
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis()
+        );
+        let resp = self.retry.send(|| {
+            Ok(self
+                .client
+                .get(&url)
+                .header("X-Requested-With", "XMLHttpRequest"))
+        })?;
+        let upload_url: UploadUrl = check_response(resp)?.json()?;
+        Ok(upload_url.url)
+    }
+
+    /// Asks the upload-link API of the React page for an upload URL.
+    fn api_upload_url(&self) -> Result<String> {
+        let url = format!(
+            "{}/api/v2.1/upload-links/{}/upload/",
+            self.server, self.token
+        );
+        let resp = self.retry.send(|| Ok(self.client.get(&url)))?;
+        let upload_url: UploadLinkUrl = check_response(resp)?.json()?;
+        Ok(upload_url.upload_link)
+    }
+
+    /// Uploads the file at `path` to the root of the link.
+    pub fn upload<P: AsRef<Path>>(&self, path: P) -> Result<FileUploadResp> {
+        let item = UploadItem {
+            path: path.as_ref().to_path_buf(),
+            relative_path: String::new(),
+        };
+        self.upload_item(
+            &item,
+            &UploadOptions::default(),
+            &(Arc::new(NoProgress) as _),
+        )
+    }
+
+    /// Uploads the file at `path` into remote directory `dir` of the link,
+    /// which is created along with its missing parents.
+    pub fn upload_to<P: AsRef<Path>>(&self, path: P, dir: &str) -> Result<FileUploadResp> {
+        let item = UploadItem {
+            path: path.as_ref().to_path_buf(),
+            relative_path: relative_dir(dir)?,
+        };
+        self.upload_item(
+            &item,
+            &UploadOptions::default(),
+            &(Arc::new(NoProgress) as _),
+        )
+    }
+
+    /// Uploads one file, in chunks if it is larger than
+    /// [`UploadOptions::chunk_size`].
+    ///
+    /// Upload links cannot list the files they hold, so
+    /// [`OnConflict::Skip`] is refused and [`OnConflict::Fail`] is only
+    /// detected once the server has stored the upload under a new name.
+    pub fn upload_item(
+        &self,
+        item: &UploadItem,
+        options: &UploadOptions,
+        progress: &Arc<dyn Progress>,
+    ) -> Result<FileUploadResp> {
+        let replace = check_on_conflict(options)?;
+        let file_name = local_file_name(&item.path)?;
+        let server = FileServer {
+            client: &self.client,
+            retry: &self.retry,
+            url: self.upload_url()?,
+            parent_dir: "/",
+        };
+        let resp = if fs::metadata(&item.path)?.len() > options.chunk_size {
+            let uploaded =
+                self.get_uploaded_bytes(&format!("/{}", item.relative_path), &file_name)?;
+            server.upload_file_chunked(item, options.chunk_size, replace, uploaded, progress)?
+        } else {
+            server.upload_file(item, replace, progress)?
+        };
+        check_stored_name(options, &item.relative_path, &file_name, resp)
+    }
+
+    /// Uploads the data read from `reader` as file `name` into
+    /// `relative_path`, without knowing its size in advance, e.g. the output
+    /// of a command.
+    ///
+    /// A stream that fits in one [`UploadOptions::chunk_size`] is sent as a
+    /// regular upload. A longer one is first written to a temporary file,
+    /// which is then sent in chunks like a large file, so that a failed chunk
+    /// is retried on its own. An interrupted upload is not resumed by a later
+    /// call, as the stream may differ.
+    pub fn upload_stream<R: Read>(
+        &self,
+        mut reader: R,
+        name: &str,
+        relative_path: &str,
+        options: &UploadOptions,
+        progress: &Arc<dyn Progress>,
+    ) -> Result<FileUploadResp> {
+        if name.is_empty() || name.contains('/') {
+            return Err(Error::Usage(format!("invalid file name {:?}", name)));
+        }
+        let replace = check_on_conflict(options)?;
+        let mut head = Vec::new();
+        (&mut reader)
+            .take(options.chunk_size)
+            .read_to_end(&mut head)?;
+        let resp = if (head.len() as u64) < options.chunk_size {
+            let url = ret_json(&self.upload_url()?);
+            let mut attempts = 0;
+            let resp = self.retry.send(|| {
+                if attempts > 0 {
+                    progress.rewind(0);
+                }
+                attempts += 1;
+                let form = upload_form("/", relative_path, replace).part(
+                    "file",
+                    progress_part(
+                        io::Cursor::new(head.clone()),
+                        head.len() as u64,
+                        name.to_string(),
+                        progress,
+                    ),
+                );
+                Ok(self.client.post(&url).multipart(form))
+            })?;
+            let mut resps: Vec<FileUploadResp> = check_response(resp)?.json()?;
+            resps
+                .pop()
+                .ok_or_else(|| Error::Rejected("file upload failed".into()))?
+        } else {
+            // Removed when closed.
+            let mut file = tempfile::tempfile()?;
+            file.write_all(&head)?;
+            io::copy(&mut reader, &mut file)?;
+            let server = FileServer {
+                client: &self.client,
+                retry: &self.retry,
+                url: self.upload_url()?,
+                parent_dir: "/",
+            };
+            let chunks = Chunks {
+                file,
+                file_name: name,
+                relative_path,
+                chunk_size: options.chunk_size,
+                replace,
+            };
+            server.upload_chunks(chunks, 0, progress)?
+        };
+        check_stored_name(options, relative_path, name, resp)
+    }
+
+    /// Asks the server how much of an interrupted chunked upload it already
+    /// has.
+    fn get_uploaded_bytes(&self, parent_dir: &str, file_name: &str) -> Result<u64> {
+        let url = format!(
+            "{}/api/v2.1/upload-links/{}/file-uploaded-bytes/",
+            self.server, self.token
+        );
+        let resp = self.retry.send(|| {
+            Ok(self
+                .client
+                .get(&url)
+                .query(&[("parent_dir", parent_dir), ("file_name", file_name)]))
+        })?;
+        let uploaded: UploadedBytes = check_response(resp)?.json()?;
+        Ok(uploaded.uploaded_bytes)
+    }
+}
+
+/// Asks the file server to describe the stored files in JSON; otherwise it
+/// only answers with the id of the file as plain text.
+fn ret_json(url: &str) -> String {
+    let separator = if url.contains('?') { '&' } else { '?' };
+    format!("{}{}ret-json=1", url, separator)
+}
+
+/// A file to upload in pieces, stored as `file_name` below `relative_path`.
+struct Chunks<'a> {
+    file: fs::File,
+    file_name: &'a str,
+    relative_path: &'a str,
+    chunk_size: u64,
+    replace: bool,
+}
+
+/// A URL of the file server handed out for uploads, and the directory the
+/// `relative_path` of the upload form starts from.
+struct FileServer<'a> {
+    client: &'a Client,
+    retry: &'a RetryPolicy,
+    url: String,
+    parent_dir: &'a str,
+}
+
+impl FileServer<'_> {
+    fn upload_file(
+        &self,
+        item: &UploadItem,
+        replace: bool,
+        progress: &Arc<dyn Progress>,
+    ) -> Result<FileUploadResp> {
+        let file_name = local_file_name(&item.path)?;
+        let url = ret_json(&self.url);
+        let mut attempts = 0;
+        let resp = self.retry.send(|| {
+            if attempts > 0 {
+                progress.rewind(0);
+            }
+            attempts += 1;
+            let file = fs::File::open(&item.path)?;
+            let length = file.metadata()?.len();
+            let form = upload_form(self.parent_dir, &item.relative_path, replace).part(
+                "file",
+                progress_part(file, length, file_name.clone(), progress),
+            );
+            Ok(self.client.post(&url).multipart(form))
+        })?;
+        let mut resps: Vec<FileUploadResp> = check_response(resp)?.json()?;
+        resps
+            .pop()
+            .ok_or_else(|| Error::Rejected("file upload failed".into()))
+    }
+
+    /// Uploads a file in `chunk_size` pieces with Seafile's resumable
+    /// protocol, starting after the `uploaded` bytes the server kept from an
+    /// earlier attempt.
+    fn upload_file_chunked(
+        &self,
+        item: &UploadItem,
+        chunk_size: u64,
+        replace: bool,
+        uploaded: u64,
+        progress: &Arc<dyn Progress>,
+    ) -> Result<FileUploadResp> {
+        let file_name = local_file_name(&item.path)?;
+        let file = fs::File::open(&item.path)?;
+        let chunks = Chunks {
+            file,
+            file_name: &file_name,
+            relative_path: &item.relative_path,
+            chunk_size,
+            replace,
+        };
+        self.upload_chunks(chunks, uploaded, progress)
+    }
+
+    /// Uploads the open file of `chunks` from offset `uploaded`.
+    fn upload_chunks(
+        &self,
+        chunks: Chunks,
+        uploaded: u64,
+        progress: &Arc<dyn Progress>,
+    ) -> Result<FileUploadResp> {
+        let Chunks {
+            mut file,
+            file_name,
+            relative_path,
+            chunk_size,
+            replace,
+        } = chunks;
+        let total = file.metadata()?.len();
+        let mut offset = uploaded;
+        if offset >= total {
+            offset = 0;
+        }
+        progress.resume_at(offset);
+        let url = ret_json(&self.url);
+        let content_disposition = format!(
+            "attachment; filename=\"{}\"",
+            utf8_percent_encode(file_name, URI_COMPONENT)
+        );
+        loop {
+            let mut chunk = Vec::new();
+            file.seek(SeekFrom::Start(offset))?;
+            (&mut file).take(chunk_size).read_to_end(&mut chunk)?;
+            let end = offset + chunk.len() as u64;
+            let mut attempts = 0;
+            let resp = self.retry.send(|| {
+                // A failed attempt may have reported part of the chunk.
+                if attempts > 0 {
+                    progress.rewind(offset);
+                }
+                attempts += 1;
+                let form = upload_form(self.parent_dir, relative_path, replace).part(
+                    "file",
+                    progress_part(
+                        io::Cursor::new(chunk.clone()),
+                        end - offset,
+                        file_name.to_string(),
+                        progress,
+                    ),
+                );
+                Ok(self
+                    .client
+                    .post(&url)
+                    .header(
+                        "Content-Range",
+                        format!("bytes {}-{}/{}", offset, end.max(1) - 1, total),
+                    )
+                    .header("Content-Disposition", &content_disposition)
+                    .multipart(form))
+            })?;
+            let resp = check_response(resp)?;
+            if end >= total {
+                let mut resps: Vec<FileUploadResp> = resp.json()?;
+                return resps
+                    .pop()
+                    .ok_or_else(|| Error::Rejected("file upload failed".into()));
+            }
+            offset = end;
+        }
+    }
+}
+
+impl Account {
+    /// Asks for a URL of the file server to post files into directory `dir`
+    /// of library `repo_id`.
+    fn upload_url(&self, repo_id: &str, dir: &str) -> Result<String> {
+        Ok(self
+            .send(
+                &format!("/api2/repos/{}/upload-link/", repo_id),
+                |client, url| client.get(url).query(&[("p", dir)]),
+            )?
+            .json()?)
+    }
+
+    fn get_uploaded_bytes(&self, repo_id: &str, parent_dir: &str, file_name: &str) -> Result<u64> {
+        let uploaded: UploadedBytes = self
+            .send(
+                &format!("/api/v2.1/repos/{}/file-uploaded-bytes/", repo_id),
+                |client, url| {
+                    client
+                        .get(url)
+                        .query(&[("parent_dir", parent_dir), ("file_name", file_name)])
+                },
+            )?
+            .json()?;
+        Ok(uploaded.uploaded_bytes)
+    }
+
+    /// Uploads one file into directory `dir` of library `repo_id`, below
+    /// `item.relative_path`, in chunks if it is larger than
+    /// [`UploadOptions::chunk_size`].
+    ///
+    /// Unlike upload links, the account can look for an existing file first,
+    /// so every [`OnConflict`] policy is honoured before uploading; `None`
+    /// means the file was skipped.
+    pub fn upload_item(
+        &self,
+        repo_id: &str,
+        dir: &str,
+        item: &UploadItem,
+        options: &UploadOptions,
+        progress: &Arc<dyn Progress>,
+    ) -> Result<Option<FileUploadResp>> {
+        let dir = normalize_path(dir);
+        let file_name = local_file_name(&item.path)?;
+        let parent_dir = normalize_path(&format!("{}/{}", dir, item.relative_path));
+        let remote = normalize_path(&format!("{}/{}", parent_dir, file_name));
+        let replace = match options.on_conflict {
+            OnConflict::Rename => false,
+            OnConflict::Overwrite => true,
+            on_conflict => match self.stat(repo_id, &remote)? {
+                None => false,
+                Some(_) if on_conflict == OnConflict::Skip => return Ok(None),
+                Some(_) => return Err(Error::Conflict(format!("{} already exists", remote))),
+            },
+        };
+        let server = FileServer {
+            client: self.client(),
+            retry: self.retry(),
+            url: self.upload_url(repo_id, &dir)?,
+            parent_dir: &dir,
+        };
+        let resp = if fs::metadata(&item.path)?.len() > options.chunk_size {
+            let uploaded = self.get_uploaded_bytes(repo_id, &parent_dir, &file_name)?;
+            server.upload_file_chunked(item, options.chunk_size, replace, uploaded, progress)?
+        } else {
+            server.upload_file(item, replace, progress)?
+        };
+        Ok(Some(resp))
+    }
+}
@@ -0,0 +1,372 @@
+use crate::{
+    error::{Error, Result},
+    progress::Progress,
+    sync::{FileState, SyncState},
+    upload::{FileUploadResp, UploadItem, UploadLink, UploadOptions},
+};
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// What happened to a file below the watched directory.
+enum Change {
+    /// The file is being written.
+    Written(PathBuf),
+    /// The file was closed after writing, or moved in whole.
+    Closed(PathBuf),
+}
+
+/// Watches a directory and its subdirectories with inotify for complete
+/// files: files closed after writing or moved in, then left alone for a
+/// while.
+pub struct DirWatcher {
+    changes: mpsc::Receiver<Result<Change>>,
+    /// Files seen changing, by whether they were closed and when they last
+    /// changed.
+    pending: HashMap<PathBuf, (bool, Instant)>,
+    debounce: Duration,
+}
+
+impl DirWatcher {
+    /// Starts watching `dir`. Files count as complete once `debounce` passed
+    /// without them changing again; the files already in `dir` count as
+    /// closed when the watch starts, so they are reported once complete too.
+    pub fn new(dir: &Path, debounce: Duration) -> Result<Self> {
+        let mut inotify = Inotify::init()?;
+        let mut dirs = HashMap::new();
+        let (tx, rx) = mpsc::channel();
+        add_watches(&mut inotify, dir, &mut dirs, &tx)?;
+        thread::spawn(move || read_changes(inotify, dirs, tx));
+        Ok(DirWatcher {
+            changes: rx,
+            pending: HashMap::new(),
+            debounce,
+        })
+    }
+
+    /// Waits until files are complete and returns them in order.
+    pub fn next_complete(&mut self) -> Result<Vec<PathBuf>> {
+        loop {
+            let now = Instant::now();
+            let mut complete: Vec<PathBuf> = self
+                .pending
+                .iter()
+                .filter(|(_, (closed, at))| *closed && now - *at >= self.debounce)
+                .map(|(path, _)| path.clone())
+                .collect();
+            if !complete.is_empty() {
+                for path in &complete {
+                    self.pending.remove(path);
+                }
+                complete.sort();
+                return Ok(complete);
+            }
+            // Wake up when the next closed file is due.
+            let due = self
+                .pending
+                .values()
+                .filter(|(closed, _)| *closed)
+                .map(|(_, at)| (*at + self.debounce).saturating_duration_since(now))
+                .min();
+            let change = match due {
+                Some(due) => match self.changes.recv_timeout(due) {
+                    Ok(change) => change,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => return Err(stopped()),
+                },
+                None => self.changes.recv().map_err(|_| stopped())?,
+            }?;
+            let (path, closed) = match change {
+                Change::Written(path) => (path, false),
+                Change::Closed(path) => (path, true),
+            };
+            self.pending.insert(path, (closed, Instant::now()));
+        }
+    }
+}
+
+fn stopped() -> Error {
+    Error::Local("the directory watcher stopped".into())
+}
+
+/// Watches `dir` and its subdirectories. The files already in them are sent
+/// to `tx`, as they may have been written before the watch started.
+fn add_watches(
+    inotify: &mut Inotify,
+    dir: &Path,
+    dirs: &mut HashMap<WatchDescriptor, PathBuf>,
+    tx: &mpsc::Sender<Result<Change>>,
+) -> Result<()> {
+    let local_error = |e| Error::Local(format!("{}: {}", dir.display(), e));
+    let wd = inotify
+        .add_watch(
+            dir,
+            WatchMask::CREATE | WatchMask::MODIFY | WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO,
+        )
+        .map_err(local_error)?;
+    dirs.insert(wd, dir.to_path_buf());
+    for entry in fs::read_dir(dir).map_err(local_error)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            add_watches(inotify, &path, dirs, tx)?;
+        } else {
+            let _ = tx.send(Ok(Change::Closed(path)));
+        }
+    }
+    Ok(())
+}
+
+/// Reads the events of `inotify` until the watcher is dropped, sending the
+/// changed files to `tx` and watching new subdirectories too.
+fn read_changes(
+    mut inotify: Inotify,
+    mut dirs: HashMap<WatchDescriptor, PathBuf>,
+    tx: mpsc::Sender<Result<Change>>,
+) {
+    let mut buffer = [0; 4096];
+    loop {
+        let events = match inotify.read_events_blocking(&mut buffer) {
+            Ok(events) => events,
+            Err(e) => {
+                let _ = tx.send(Err(e.into()));
+                return;
+            }
+        };
+        let mut new_dirs = Vec::new();
+        for event in events {
+            if event.mask.contains(EventMask::IGNORED) {
+                dirs.remove(&event.wd);
+                continue;
+            }
+            let path = match (dirs.get(&event.wd), event.name) {
+                (Some(dir), Some(name)) => dir.join(name),
+                _ => continue,
+            };
+            let change = if event.mask.contains(EventMask::ISDIR) {
+                if event
+                    .mask
+                    .intersects(EventMask::CREATE | EventMask::MOVED_TO)
+                {
+                    new_dirs.push(path);
+                }
+                continue;
+            } else if event
+                .mask
+                .intersects(EventMask::CLOSE_WRITE | EventMask::MOVED_TO)
+            {
+                Change::Closed(path)
+            } else {
+                Change::Written(path)
+            };
+            if tx.send(Ok(change)).is_err() {
+                return;
+            }
+        }
+        for dir in new_dirs {
+            if let Err(e) = add_watches(&mut inotify, &dir, &mut dirs, &tx) {
+                let _ = tx.send(Err(e));
+                return;
+            }
+        }
+    }
+}
+
+/// A directory whose files are uploaded to an upload link as they are
+/// complete, e.g. by `upload --watch`.
+pub struct Watch<'a> {
+    pub dir: &'a Path,
+    /// The remote directory files are uploaded into, as `relative_path`.
+    pub dest: &'a str,
+    pub debounce: Duration,
+    /// How many files are uploaded at once at most.
+    pub jobs: usize,
+    /// Records what was uploaded, so that restarts upload nothing twice.
+    pub state_path: &'a Path,
+}
+
+/// A file of the watched directory that was uploaded.
+pub struct WatchUpload<P> {
+    pub item: UploadItem,
+    pub resp: FileUploadResp,
+    /// The progress of the upload, as made for it by the caller.
+    pub progress: Arc<P>,
+}
+
+/// The state of a watch, shared by its workers.
+struct WatchState {
+    /// The files as they were uploaded.
+    uploaded: SyncState,
+    /// The files being uploaded, by whether they completed again meanwhile.
+    uploading: BTreeMap<String, bool>,
+}
+
+impl Watch<'_> {
+    /// Uploads the files appearing in the watched directory as they are
+    /// complete, after those that appeared since the last run, until the
+    /// watch fails.
+    ///
+    /// `progress` makes the progress of uploading a file, given its path
+    /// relative to the watched directory and its size. Each upload, or why it
+    /// failed, is passed to `report`; failed uploads are left for the next
+    /// change or restart.
+    pub fn run<P, F, R>(
+        &self,
+        upload_link: &UploadLink,
+        options: &UploadOptions,
+        progress: F,
+        report: R,
+    ) -> Result<()>
+    where
+        P: Progress + 'static,
+        F: Fn(&str, u64) -> Arc<P> + Sync,
+        R: Fn(Result<WatchUpload<P>>) + Sync,
+    {
+        let state = Mutex::new(WatchState {
+            uploaded: SyncState::load(self.state_path)?,
+            uploading: BTreeMap::new(),
+        });
+        // Reports the files already there like new ones.
+        let mut watcher = DirWatcher::new(self.dir, self.debounce)?;
+        let (tx, rx) = mpsc::channel::<PathBuf>();
+        let rx = Mutex::new(rx);
+        thread::scope(|scope| {
+            for _ in 0..self.jobs {
+                scope.spawn(|| loop {
+                    let path = match rx.lock().unwrap().recv() {
+                        Ok(path) => path,
+                        Err(_) => return,
+                    };
+                    let result =
+                        self.upload(upload_link, &path, options, &state, &progress, &report);
+                    if let Err(e) =
+                        result.and_then(|()| state.lock().unwrap().uploaded.save(self.state_path))
+                    {
+                        report(Err(e));
+                    }
+                });
+            }
+            let result = loop {
+                match watcher.next_complete() {
+                    Ok(paths) => {
+                        for path in paths {
+                            let _ = tx.send(path);
+                        }
+                    }
+                    Err(e) => break Err(e),
+                }
+            };
+            drop(tx);
+            result
+        })
+    }
+
+    /// Uploads file `path` unless it is gone or was uploaded as it is. A file
+    /// another worker is uploading is left to that worker, which uploads it
+    /// again once done if it changed meanwhile.
+    fn upload<P, F, R>(
+        &self,
+        upload_link: &UploadLink,
+        path: &Path,
+        options: &UploadOptions,
+        state: &Mutex<WatchState>,
+        progress: &F,
+        report: &R,
+    ) -> Result<()>
+    where
+        P: Progress + 'static,
+        F: Fn(&str, u64) -> Arc<P>,
+        R: Fn(Result<WatchUpload<P>>),
+    {
+        let key = self.key(path);
+        {
+            let mut state = state.lock().unwrap();
+            if let Some(again) = state.uploading.get_mut(&key) {
+                *again = true;
+                return Ok(());
+            }
+            state.uploading.insert(key.clone(), false);
+        }
+        loop {
+            let result = self.upload_changed(upload_link, path, options, state, progress, report);
+            let mut state = state.lock().unwrap();
+            let again = state.uploading.remove(&key).unwrap_or(false);
+            if result.is_err() || !again {
+                return result;
+            }
+            state.uploading.insert(key.clone(), false);
+        }
+    }
+
+    /// The path of `path` relative to the watched directory, which keys the
+    /// state file.
+    fn key(&self, path: &Path) -> String {
+        let relative = path.strip_prefix(self.dir).unwrap_or(path);
+        relative.to_string_lossy().into_owned()
+    }
+
+    /// Uploads file `path` and records it, unless it is gone or was uploaded
+    /// as it is.
+    fn upload_changed<P, F, R>(
+        &self,
+        upload_link: &UploadLink,
+        path: &Path,
+        options: &UploadOptions,
+        state: &Mutex<WatchState>,
+        progress: &F,
+        report: &R,
+    ) -> Result<()>
+    where
+        P: Progress + 'static,
+        F: Fn(&str, u64) -> Arc<P>,
+        R: Fn(Result<WatchUpload<P>>),
+    {
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) if metadata.is_file() => metadata,
+            _ => return Ok(()),
+        };
+        let relative = path.strip_prefix(self.dir).unwrap_or(path);
+        let key = self.key(path);
+        if let Some(last) = state.lock().unwrap().uploaded.files.get(&key) {
+            if last.matches(&metadata) {
+                return Ok(());
+            }
+        }
+        let subdir = relative
+            .parent()
+            .map(|parent| parent.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let item = UploadItem {
+            path: path.to_path_buf(),
+            relative_path: match (self.dest, subdir.as_str()) {
+                (dest, "") => dest.to_string(),
+                ("", subdir) => subdir.to_string(),
+                (dest, subdir) => format!("{}/{}", dest, subdir),
+            },
+        };
+        let progress = progress(&key, metadata.len());
+        let resp =
+            upload_link.upload_item(&item, options, &(progress.clone() as Arc<dyn Progress>))?;
+        let id = resp.id.clone();
+        report(Ok(WatchUpload {
+            item,
+            resp,
+            progress,
+        }));
+        state
+            .lock()
+            .unwrap()
+            .uploaded
+            .files
+            .insert(key, FileState::new(&metadata, id));
+        Ok(())
+    }
+}
@@ -0,0 +1,145 @@
+mod mock;
+
+use mock::{Layout, MockServer, Options};
+use std::{
+    fs,
+    path::PathBuf,
+    process::{Command, Output},
+};
+
+/// A directory of its own for each test, holding the file to upload and an
+/// empty configuration directory.
+fn test_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir()
+        .join(format!("seaf-web-cli-tests-{}", std::process::id()))
+        .join(test);
+    fs::create_dir_all(dir.join("config")).unwrap();
+    fs::write(dir.join("report.txt"), b"quarterly numbers").unwrap();
+    dir
+}
+
+/// Runs `seaf-web upload` with `args` of `report.txt` to the link of `server`.
+fn upload(server: &MockServer, test: &str, password: Option<&str>, args: &[&str]) -> Output {
+    let dir = test_dir(test);
+    let mut command = Command::new(env!("CARGO_BIN_EXE_seaf-web"));
+    command
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .env_remove("SEAF_WEB_SERVER")
+        .env_remove("SEAF_WEB_PASSWORD")
+        .args(["--retries", "0"])
+        .arg("upload")
+        .args(args)
+        .arg(server.link())
+        .arg(dir.join("report.txt"));
+    if let Some(password) = password {
+        command.env("SEAF_WEB_PASSWORD", password);
+    }
+    command.output().unwrap()
+}
+
+#[test]
+fn uploads_and_prints_the_stored_file() {
+    let server = MockServer::start(Options {
+        password: Some("secret"),
+        ..Options::default()
+    });
+    let output = upload(&server, "success", Some("secret"), &["--output", "tsv"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let fields: Vec<&str> = stdout.trim_end().split('\t').collect();
+    assert_eq!(fields[..3], ["0".repeat(40).as_str(), "report.txt", "17"]);
+    assert_eq!(server.uploads()[0].content, b"quarterly numbers");
+}
+
+#[test]
+fn uploads_through_react_page() {
+    let server = MockServer::start(Options {
+        password: Some("secret"),
+        version: Some("11.0.12"),
+        layout: Layout::React,
+        ..Options::default()
+    });
+    let output = upload(&server, "react", Some("secret"), &[]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(server.uploads()[0].content, b"quarterly numbers");
+}
+
+#[test]
+fn exits_4_on_wrong_password() {
+    let server = MockServer::start(Options {
+        password: Some("secret"),
+        ..Options::default()
+    });
+    let output = upload(&server, "wrong-password", Some("guess"), &[]);
+    assert_eq!(output.status.code(), Some(4), "{:?}", output);
+    assert!(server.uploads().is_empty());
+}
+
+#[test]
+fn exits_6_on_unknown_page() {
+    let server = MockServer::start(Options {
+        layout: Layout::Unknown,
+        ..Options::default()
+    });
+    let output = upload(&server, "unknown-page", None, &["--output", "json"]);
+    assert_eq!(output.status.code(), Some(6), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(r#""kind":"layout""#), "{}", stderr);
+}
+
+#[test]
+fn exits_8_on_failed_upload() {
+    let server = MockServer::start(Options {
+        fail_uploads: true,
+        ..Options::default()
+    });
+    let output = upload(&server, "failed-upload", None, &[]);
+    assert_eq!(output.status.code(), Some(8), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("500"), "{}", stderr);
+}
+
+#[test]
+fn exits_9_naming_the_stored_copy() {
+    let server = MockServer::start(Options {
+        existing: &["report.txt"],
+        ..Options::default()
+    });
+    let output = upload(&server, "conflict", None, &["--on-conflict", "fail"]);
+    assert_eq!(output.status.code(), Some(9), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains(&format!(
+            "stored as /report (1).txt (id {})",
+            "0".repeat(40)
+        )),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn refuses_to_skip_existing_files() {
+    let server = MockServer::start(Options::default());
+    let output = upload(&server, "skip", None, &["--on-conflict", "skip"]);
+    assert_eq!(output.status.code(), Some(2), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("'skip' isn't a valid value"), "{}", stderr);
+    assert!(server.uploads().is_empty());
+}
+
+#[test]
+fn checks_arguments_before_the_password() {
+    let server = MockServer::start(Options {
+        password: Some("secret"),
+        ..Options::default()
+    });
+    let output = upload(&server, "jobs", Some("wrong"), &["--jobs", "0"]);
+    assert_eq!(output.status.code(), Some(2), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("jobs must be a positive number"),
+        "{}",
+        stderr
+    );
+}
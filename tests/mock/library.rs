@@ -0,0 +1,271 @@
+//! The part of the web API of an account that syncs use, serving a single
+//! library kept in memory.
+
+use super::{header, json, parse_upload, ret_json};
+use std::{collections::BTreeMap, io::Cursor, sync::Mutex};
+use tiny_http::{Method, Request, Response};
+
+pub const ACCOUNT_TOKEN: &str = "0123456789abcdef0123456789abcdef01234567";
+pub const LIBRARY_ID: &str = "87654321-4321-4321-4321-cba987654321";
+
+#[derive(Clone)]
+enum Entry {
+    Dir,
+    File { content: Vec<u8>, mtime: i64 },
+}
+
+/// The files and directories of the library by absolute path.
+pub struct Library {
+    entries: BTreeMap<String, Entry>,
+    /// Stands in for the clock, so that every change has a later mtime.
+    now: i64,
+}
+
+impl Default for Library {
+    fn default() -> Self {
+        let mut entries = BTreeMap::new();
+        entries.insert("/".to_string(), Entry::Dir);
+        Library {
+            entries,
+            now: 1_600_000_000,
+        }
+    }
+}
+
+/// Seafile names files by the SHA-1 of their content, more or less.
+fn file_id(content: &[u8]) -> String {
+    sha1_smol::Sha1::from(content).digest().to_string()
+}
+
+fn parent(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(0) | None => "/",
+        Some(slash) => &path[..slash],
+    }
+}
+
+fn join(dir: &str, name: &str) -> String {
+    normalize(&format!("{}/{}", dir, name))
+}
+
+fn normalize(path: &str) -> String {
+    let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+    format!("/{}", components.join("/"))
+}
+
+impl Library {
+    /// Writes file `path`, creating its parents.
+    pub fn put(&mut self, path: &str, content: &[u8]) {
+        let path = normalize(path);
+        self.mkdirs(parent(&path));
+        self.now += 1;
+        self.entries.insert(
+            path,
+            Entry::File {
+                content: content.to_vec(),
+                mtime: self.now,
+            },
+        );
+    }
+
+    /// Removes `path` and everything below it.
+    pub fn remove(&mut self, path: &str) {
+        let path = normalize(path);
+        let below = format!("{}/", path);
+        self.entries
+            .retain(|key, _| *key != path && !key.starts_with(&below));
+    }
+
+    pub fn read(&self, path: &str) -> Option<Vec<u8>> {
+        match self.entries.get(&normalize(path)) {
+            Some(Entry::File { content, .. }) => Some(content.clone()),
+            _ => None,
+        }
+    }
+
+    /// The paths of all files, in order.
+    pub fn files(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .filter(|(_, entry)| matches!(entry, Entry::File { .. }))
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    fn is_dir(&self, path: &str) -> bool {
+        matches!(self.entries.get(path), Some(Entry::Dir))
+    }
+
+    fn mkdirs(&mut self, dir: &str) {
+        let mut prefix = String::new();
+        for component in dir.split('/').filter(|c| !c.is_empty()) {
+            prefix.push('/');
+            prefix.push_str(component);
+            self.entries.entry(prefix.clone()).or_insert(Entry::Dir);
+        }
+    }
+
+    fn list(&self, dir: &str) -> String {
+        let dirents: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(path, _)| *path != "/" && parent(path) == dir)
+            .map(|(path, entry)| {
+                let name = &path[path.rfind('/').unwrap() + 1..];
+                let (kind, id, size, mtime) = match entry {
+                    Entry::Dir => ("dir", "0".repeat(40), 0, self.now),
+                    Entry::File { content, mtime } => {
+                        ("file", file_id(content), content.len(), *mtime)
+                    }
+                };
+                format!(
+                    r#"{{"name": {:?}, "type": "{}", "id": "{}", "size": {}, "mtime": {}, "permission": "rw"}}"#,
+                    name, kind, id, size, mtime
+                )
+            })
+            .collect();
+        format!("[{}]", dirents.join(", "))
+    }
+
+    /// The first name of `name`, `name (1)`, ... not taken in `dir`.
+    fn free_name(&self, dir: &str, name: &str) -> String {
+        let (stem, ext) = match name.rfind('.') {
+            Some(dot) if dot > 0 => (&name[..dot], &name[dot..]),
+            _ => (name, ""),
+        };
+        (0..)
+            .map(|n| match n {
+                0 => name.to_string(),
+                n => format!("{} ({}){}", stem, n, ext),
+            })
+            .find(|name| !self.entries.contains_key(&join(dir, name)))
+            .unwrap()
+    }
+}
+
+/// Decodes a `application/x-www-form-urlencoded` value.
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap();
+                decoded.push(u8::from_str_radix(hex, 16).unwrap());
+                i += 2;
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8(decoded).unwrap()
+}
+
+fn param(query: &str, name: &str) -> Option<String> {
+    query
+        .split('&')
+        .filter_map(|param| param.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| decode(value))
+}
+
+fn not_found(what: &str) -> Response<Cursor<Vec<u8>>> {
+    json(&format!(r#"{{"error_msg": "{} not found."}}"#, what)).with_status_code(404)
+}
+
+/// Answers the requests of the library API, or returns `None` for requests
+/// of other parts of the mock.
+pub(super) fn handle(
+    request: &Request,
+    path: &str,
+    query: &str,
+    body: &[u8],
+    url: &str,
+    library: &Mutex<Library>,
+) -> Option<Response<Cursor<Vec<u8>>>> {
+    let repo_api = format!("/api2/repos/{}/", LIBRARY_ID);
+    let repo_api_v21 = format!("/api/v2.1/repos/{}/", LIBRARY_ID);
+    let api = path.starts_with(&repo_api) || path.starts_with("/api/v2.1/repos/");
+    if api && header(request, "Authorization") != Some(&format!("Token {}", ACCOUNT_TOKEN)) {
+        return Some(json(r#"{"detail": "Invalid token"}"#).with_status_code(401));
+    }
+    let mut library = library.lock().unwrap();
+    let p = param(query, "p").map(|p| normalize(&p));
+    let response = match (request.method(), path.strip_prefix(&repo_api)) {
+        (Method::Get, Some("dir/")) => {
+            let dir = p.unwrap_or_else(|| "/".into());
+            if library.is_dir(&dir) {
+                json(&library.list(&dir))
+            } else {
+                not_found("Folder")
+            }
+        }
+        // The file server only uploads into existing directories, and below
+        // them along `relative_path`.
+        (Method::Get, Some("upload-link/")) => match p {
+            Some(dir) if library.is_dir(&dir) => json(&format!(r#""{}/upload-api/library""#, url)),
+            _ => not_found("Folder"),
+        },
+        (Method::Get, Some("file/")) => match p.and_then(|path| library.read(&path)) {
+            Some(content) => json(&format!(r#""{}/files/{}""#, url, file_id(&content))),
+            None => not_found("File"),
+        },
+        _ => match (request.method(), path.strip_prefix(&repo_api_v21), path) {
+            (Method::Post, Some("dir/"), _) => {
+                let dir = p.unwrap_or_default();
+                if !library.is_dir(parent(&dir)) || library.entries.contains_key(&dir) {
+                    return Some(json(r#"{"error_msg": "Invalid path."}"#).with_status_code(400));
+                }
+                library.mkdirs(&dir);
+                json("{}")
+            }
+            (Method::Delete, _, "/api/v2.1/repos/batch-delete-item/") => {
+                let form: serde_json::Value = serde_json::from_slice(body).unwrap();
+                let parent_dir = form["parent_dir"].as_str().unwrap();
+                for name in form["dirents"].as_array().unwrap() {
+                    library.remove(&join(parent_dir, name.as_str().unwrap()));
+                }
+                json(r#"{"success": true}"#)
+            }
+            (Method::Post, _, "/upload-api/library") => {
+                let content_type = header(request, "Content-Type").unwrap_or_default();
+                let upload = match parse_upload(content_type, body) {
+                    Some(upload) if library.is_dir(&normalize(&upload.parent_dir)) => upload,
+                    _ => return Some(json(r#"{"error": "Invalid form."}"#).with_status_code(400)),
+                };
+                let dir = join(&upload.parent_dir, &upload.relative_path);
+                let name = if upload.replace {
+                    upload.file_name.clone()
+                } else {
+                    library.free_name(&dir, &upload.file_name)
+                };
+                library.put(&join(&dir, &name), &upload.content);
+                let id = file_id(&upload.content);
+                if !ret_json(query) {
+                    return Some(Response::from_string(id));
+                }
+                json(&format!(
+                    r#"[{{"name": {:?}, "id": "{}", "size": {}}}]"#,
+                    name,
+                    id,
+                    upload.content.len()
+                ))
+            }
+            (Method::Get, _, path) if path.starts_with("/files/") => {
+                let id = &path["/files/".len()..];
+                let content = library.entries.values().find_map(|entry| match entry {
+                    Entry::File { content, .. } if file_id(content) == id => Some(content.clone()),
+                    _ => None,
+                });
+                match content {
+                    Some(content) => Response::from_data(content),
+                    None => not_found("File"),
+                }
+            }
+            _ => return None,
+        },
+    };
+    Some(response)
+}
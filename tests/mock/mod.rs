@@ -0,0 +1,367 @@
+//! An in-process mock of the pages and file server of a Seafile upload link,
+//! and of the library API of an account, as far as seaf-web uses them.
+
+// Each test crate uses a part of the mock only.
+#![allow(dead_code)]
+
+pub mod library;
+
+use library::Library;
+use std::{
+    sync::{Arc, Mutex, MutexGuard},
+    thread::{self, JoinHandle},
+};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+pub const TOKEN: &str = "0123456789abcdef0123";
+pub const REPO_ID: &str = "12345678-1234-1234-1234-123456789abc";
+const CSRF_TOKEN: &str = "csrf0123";
+const SESSION_COOKIE: &str = "sessionid=unlocked";
+
+/// What the link page looks like.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Layout {
+    /// The jQuery-era page with the upload script of Seafile 6 to 8.
+    Script,
+    /// The React page of Seafile 9 and later, with `window.shared`.
+    React,
+    /// A page with neither the password form nor the upload script.
+    Unknown,
+}
+
+#[derive(Clone)]
+pub struct Options {
+    /// The password of the link, if it is protected.
+    pub password: Option<&'static str>,
+    /// The version `/api2/server-info/` reports; it answers 404 if not set.
+    pub version: Option<&'static str>,
+    pub layout: Layout,
+    /// Uploads fail with 500 Internal Server Error.
+    pub fail_uploads: bool,
+    /// Names of files that already exist in the root of the link, so that
+    /// uploads of the same name are stored as `name (1)` unless replacing.
+    pub existing: &'static [&'static str],
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            password: None,
+            version: None,
+            layout: Layout::Script,
+            fail_uploads: false,
+            existing: &[],
+        }
+    }
+}
+
+/// A file the mock received.
+#[derive(Clone, Debug)]
+pub struct Upload {
+    pub parent_dir: String,
+    pub relative_path: String,
+    pub file_name: String,
+    pub replace: bool,
+    pub content: Vec<u8>,
+}
+
+/// A mock server on an ephemeral port of localhost, stopped when dropped.
+pub struct MockServer {
+    pub url: String,
+    uploads: Arc<Mutex<Vec<Upload>>>,
+    library: Arc<Mutex<Library>>,
+    server: Arc<Server>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    pub fn start(options: Options) -> Self {
+        let server = Arc::new(Server::http("127.0.0.1:0").unwrap());
+        let url = format!("http://{}", server.server_addr().to_ip().unwrap());
+        let uploads = Arc::new(Mutex::new(Vec::new()));
+        let library = Arc::new(Mutex::new(Library::default()));
+        let thread = {
+            let server = server.clone();
+            let url = url.clone();
+            let uploads = uploads.clone();
+            let library = library.clone();
+            thread::spawn(move || {
+                while let Ok(request) = server.recv() {
+                    handle(request, &url, &options, &uploads, &library);
+                }
+            })
+        };
+        MockServer {
+            url,
+            uploads,
+            library,
+            server,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn link(&self) -> String {
+        format!("{}/u/d/{}/", self.url, TOKEN)
+    }
+
+    pub fn uploads(&self) -> Vec<Upload> {
+        self.uploads.lock().unwrap().clone()
+    }
+
+    /// The library served to the account of [`library::ACCOUNT_TOKEN`].
+    pub fn library(&self) -> MutexGuard<'_, Library> {
+        self.library.lock().unwrap()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn html(body: &str) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_string(format!("<!DOCTYPE html><html><body>{}</body></html>", body))
+        .with_header(Header::from_bytes("Content-Type", "text/html; charset=utf-8").unwrap())
+}
+
+fn json(body: &str) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_string(body)
+        .with_header(Header::from_bytes("Content-Type", "application/json").unwrap())
+}
+
+fn password_form(error: bool) -> String {
+    format!(
+        r#"<form id="share-passwd-form" method="post" action="">
+<input type="hidden" name="csrfmiddlewaretoken" value="{}">
+<input type="password" name="password">
+{}</form>"#,
+        CSRF_TOKEN,
+        if error {
+            r#"<p class="error">Please enter a correct password.</p>"#
+        } else {
+            ""
+        }
+    )
+}
+
+fn upload_page(layout: Layout) -> String {
+    match layout {
+        Layout::Script => format!(
+            r#"<div id="upload-link"></div>
+<script type="text/javascript">
+$('#file-upload').fileupload({{ url: '/ajax/u/d/{}/upload/?r={}' }});
+</script>"#,
+            TOKEN, REPO_ID
+        ),
+        Layout::React => format!(
+            r#"<div id="wrapper"></div>
+<script type="text/javascript">
+window.shared = {{
+  pageOptions: {{
+    dirName: 'Inbox',
+    sharedBy: 'Alice',
+    noQuota: false,
+    token: '{}',
+    repoID: '{}',
+    path: '/Inbox/',
+  }}
+}};
+</script>
+<script src="/media/assets/frontend/js/uploadLink.chunk.js"></script>"#,
+            TOKEN, REPO_ID
+        ),
+        Layout::Unknown => "<p>Welcome to the new share page.</p>".to_string(),
+    }
+}
+
+fn header<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str())
+}
+
+/// Whether the query of an upload asks for the stored files in JSON.
+fn ret_json(query: &str) -> bool {
+    query.split('&').any(|param| param == "ret-json=1")
+}
+
+fn handle(
+    mut request: Request,
+    url: &str,
+    options: &Options,
+    uploads: &Mutex<Vec<Upload>>,
+    library: &Mutex<Library>,
+) {
+    let unlocked = options.password.is_none()
+        || header(&request, "Cookie").is_some_and(|cookie| cookie.contains(SESSION_COOKIE));
+    let (path, query) = match request.url().split_once('?') {
+        Some((path, query)) => (path.to_string(), query.to_string()),
+        None => (request.url().to_string(), String::new()),
+    };
+    let mut body = Vec::new();
+    request.as_reader().read_to_end(&mut body).unwrap();
+    if let Some(response) = library::handle(&request, &path, &query, &body, url, library) {
+        let _ = request.respond(response);
+        return;
+    }
+    let page_path = format!("/u/d/{}/", TOKEN);
+    // Each layout only serves the upload URLs its page asks for.
+    let ajax_path = match options.layout {
+        Layout::Script => format!("/ajax/u/d/{}/upload/", TOKEN),
+        _ => String::new(),
+    };
+    let api_path = match options.layout {
+        Layout::React => format!("/api/v2.1/upload-links/{}/upload/", TOKEN),
+        _ => String::new(),
+    };
+    let uploaded_bytes_path = format!("/api/v2.1/upload-links/{}/file-uploaded-bytes/", TOKEN);
+    let response = match (request.method(), path.as_str()) {
+        (Method::Get, path) if path == page_path && unlocked => html(&upload_page(options.layout)),
+        (Method::Get, path) if path == page_path => html(&password_form(false)),
+        (Method::Post, path) if path == page_path => {
+            let form = String::from_utf8_lossy(&body);
+            let correct = options.password.is_some_and(|password| {
+                form.contains(&format!("csrfmiddlewaretoken={}", CSRF_TOKEN))
+                    && form.contains(&format!("token={}", TOKEN))
+                    && form
+                        .split('&')
+                        .any(|field| field == format!("password={}", password))
+            });
+            if correct {
+                html(&upload_page(options.layout)).with_header(
+                    Header::from_bytes("Set-Cookie", format!("{}; Path=/", SESSION_COOKIE))
+                        .unwrap(),
+                )
+            } else {
+                html(&password_form(true))
+            }
+        }
+        (Method::Get, path) if path == ajax_path && unlocked => {
+            json(&format!(r#"{{"url": "{}/upload-api/0a1b2c3d"}}"#, url))
+        }
+        (Method::Get, path) if path == ajax_path => {
+            json(r#"{"error": "Permission denied."}"#).with_status_code(403)
+        }
+        (Method::Get, path) if path == api_path && unlocked => json(&format!(
+            r#"{{"upload_link": "{}/upload-api/0a1b2c3d"}}"#,
+            url
+        )),
+        (Method::Get, path) if path == api_path => {
+            json(r#"{"error_msg": "Permission denied."}"#).with_status_code(403)
+        }
+        (Method::Get, "/api2/server-info/") => match options.version {
+            Some(version) => json(&format!(
+                r#"{{"version": "{}", "features": ["seafile-basic"]}}"#,
+                version
+            )),
+            None => html("<p>Page not found</p>").with_status_code(404),
+        },
+        (Method::Post, "/upload-api/0a1b2c3d") if options.fail_uploads => {
+            Response::from_string("Internal error\n").with_status_code(500)
+        }
+        (Method::Get, path) if path == uploaded_bytes_path => json(r#"{"uploadedBytes": 0}"#),
+        (Method::Post, "/upload-api/0a1b2c3d") => {
+            let content_type = header(&request, "Content-Type").unwrap_or_default();
+            // Chunks but the last one of a resumable upload, `bytes 0-4095/10000`.
+            let more_chunks = header(&request, "Content-Range")
+                .and_then(|range| {
+                    let (end, total) = range.strip_prefix("bytes ")?.split_once('/')?;
+                    let end: u64 = end.split_once('-')?.1.parse().ok()?;
+                    Some(end + 1 < total.parse().ok()?)
+                })
+                .unwrap_or(false);
+            match parse_upload(content_type, &body) {
+                Some(upload) if more_chunks => {
+                    uploads.lock().unwrap().push(upload);
+                    json(r#"{"success": true}"#)
+                }
+                // Without `ret-json=1` the file server only answers the id.
+                Some(upload) if !ret_json(&query) => {
+                    uploads.lock().unwrap().push(upload);
+                    Response::from_string("0".repeat(40))
+                }
+                Some(upload) => {
+                    let taken = upload.relative_path.is_empty()
+                        && !upload.replace
+                        && options.existing.contains(&upload.file_name.as_str());
+                    let name = match upload.file_name.rsplit_once('.') {
+                        Some((stem, ext)) if taken => format!("{} (1).{}", stem, ext),
+                        None if taken => format!("{} (1)", upload.file_name),
+                        _ => upload.file_name.clone(),
+                    };
+                    let response = json(&format!(
+                        r#"[{{"name": "{}", "id": "{}", "size": {}}}]"#,
+                        name,
+                        "0".repeat(40),
+                        upload.content.len()
+                    ));
+                    uploads.lock().unwrap().push(upload);
+                    response
+                }
+                None => json(r#"{"error": "Invalid form."}"#).with_status_code(400),
+            }
+        }
+        _ => html("<p>Page not found</p>").with_status_code(404),
+    };
+    let _ = request.respond(response);
+}
+
+/// Parses the `multipart/form-data` upload form.
+fn parse_upload(content_type: &str, body: &[u8]) -> Option<Upload> {
+    let boundary = format!("--{}", content_type.split("boundary=").nth(1)?);
+    let mut upload = Upload {
+        parent_dir: String::new(),
+        relative_path: String::new(),
+        file_name: String::new(),
+        replace: false,
+        content: Vec::new(),
+    };
+    let mut file = false;
+    for part in split(body, boundary.as_bytes()).into_iter().skip(1) {
+        let part = part.strip_prefix(b"\r\n")?;
+        let end = find(part, b"\r\n\r\n")?;
+        let headers = String::from_utf8_lossy(&part[..end]);
+        let value = part[end + 4..].strip_suffix(b"\r\n")?;
+        let name = attribute(&headers, "name")?;
+        match name.as_str() {
+            "file" => {
+                upload.file_name = attribute(&headers, "filename")?;
+                upload.content = value.to_vec();
+                file = true;
+            }
+            "parent_dir" => upload.parent_dir = String::from_utf8_lossy(value).into_owned(),
+            "relative_path" => upload.relative_path = String::from_utf8_lossy(value).into_owned(),
+            "replace" => upload.replace = value == b"1",
+            _ => {}
+        }
+    }
+    Some(upload).filter(|_| file)
+}
+
+fn attribute(headers: &str, name: &str) -> Option<String> {
+    let start = headers.find(&format!(" {}=\"", name))? + name.len() + 3;
+    let end = headers[start..].find('"')?;
+    Some(headers[start..start + end].to_string())
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Splits `body` at every `delimiter`, dropping the closing `--` part.
+fn split<'a>(mut body: &'a [u8], delimiter: &[u8]) -> Vec<&'a [u8]> {
+    let mut parts = Vec::new();
+    while let Some(at) = find(body, delimiter) {
+        parts.push(&body[..at]);
+        body = &body[at + delimiter.len()..];
+    }
+    parts
+}
@@ -0,0 +1,369 @@
+mod mock;
+
+use mock::{
+    library::{ACCOUNT_TOKEN, LIBRARY_ID},
+    MockServer, Options,
+};
+use seaf_web::{
+    link,
+    progress::NoProgress,
+    sync::{Compare, SyncAction, SyncOptions, SyncState},
+    Account, Error, Progress, RetryPolicy,
+};
+use std::{
+    fs,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+/// The remote directory the tests sync with.
+const DIR: &str = "/synced";
+
+/// The path of `remote` relative to [`DIR`].
+fn key(remote: &str) -> &str {
+    remote.strip_prefix(DIR).unwrap().trim_start_matches('/')
+}
+
+/// A local directory synced with [`DIR`] on a mock server.
+struct Fixture {
+    server: MockServer,
+    account: Account,
+    local: PathBuf,
+    state: SyncState,
+}
+
+impl Fixture {
+    fn new(test: &str) -> Self {
+        let local = std::env::temp_dir()
+            .join(format!("seaf-web-sync-tests-{}", std::process::id()))
+            .join(test);
+        let _ = fs::remove_dir_all(&local);
+        fs::create_dir_all(&local).unwrap();
+        let server = MockServer::start(Options::default());
+        let account = Account::with_client(
+            link::client().unwrap(),
+            RetryPolicy::none(),
+            &server.url,
+            ACCOUNT_TOKEN,
+        );
+        Fixture {
+            server,
+            account,
+            local,
+            state: SyncState::default(),
+        }
+    }
+
+    fn write(&self, key: &str, content: &str) {
+        let path = self.local.join(key);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn read(&self, key: &str) -> String {
+        fs::read_to_string(self.local.join(key)).unwrap()
+    }
+
+    fn put_remote(&self, key: &str, content: &str) {
+        self.server
+            .library()
+            .put(&format!("{}/{}", DIR, key), content.as_bytes());
+    }
+
+    fn read_remote(&self, key: &str) -> String {
+        let content = self.server.library().read(&format!("{}/{}", DIR, key));
+        String::from_utf8(content.unwrap()).unwrap()
+    }
+
+    /// Sets the modification time of local file `key`, as the mock library
+    /// stamps its files in 2020.
+    fn touch(&self, key: &str, secs: u64) {
+        fs::File::options()
+            .write(true)
+            .open(self.local.join(key))
+            .unwrap()
+            .set_modified(UNIX_EPOCH + Duration::from_secs(secs))
+            .unwrap();
+    }
+
+    /// Describes a step by path relative to the synced directories.
+    fn describe(&self, action: &SyncAction) -> String {
+        match action {
+            SyncAction::Mkdir { remote } => format!("mkdir {}", remote),
+            SyncAction::Upload { remote, .. } => format!("upload {}", key(remote)),
+            SyncAction::Delete { remote } => format!("delete {}", key(remote)),
+            SyncAction::Download { remote, .. } => format!("download {}", key(remote)),
+            SyncAction::DeleteLocal { remote, .. } => format!("delete-local {}", key(remote)),
+            SyncAction::Conflict { remote, copy, .. } => format!(
+                "conflict {} as {}",
+                key(remote),
+                copy.strip_prefix(&self.local).unwrap().display()
+            ),
+        }
+    }
+
+    /// Plans a sync and describes its steps.
+    fn plan(&mut self) -> (Vec<SyncAction>, Vec<String>) {
+        let plan = self
+            .account
+            .plan_two_way(&self.local, LIBRARY_ID, DIR, &mut self.state)
+            .unwrap();
+        let steps = plan.iter().map(|action| self.describe(action)).collect();
+        (plan, steps)
+    }
+
+    /// Plans and applies a sync, and returns its steps.
+    fn sync(&mut self) -> Vec<String> {
+        let (plan, steps) = self.plan();
+        let progress: Arc<dyn Progress> = Arc::new(NoProgress);
+        for action in &plan {
+            self.account
+                .apply_two_way(LIBRARY_ID, DIR, action, 8 << 20, &progress, &mut self.state)
+                .unwrap();
+        }
+        steps
+    }
+
+    /// Plans and applies a one-way sync, and returns its steps.
+    fn sync_one_way(&self, options: SyncOptions) -> Vec<String> {
+        let plan = self
+            .account
+            .plan_sync(&self.local, LIBRARY_ID, DIR, &options)
+            .unwrap();
+        let progress: Arc<dyn Progress> = Arc::new(NoProgress);
+        for action in &plan {
+            self.account
+                .apply_sync(LIBRARY_ID, action, 8 << 20, &progress)
+                .unwrap();
+        }
+        plan.iter().map(|action| self.describe(action)).collect()
+    }
+}
+
+#[test]
+fn first_sync_uploads_into_new_directories() {
+    let mut fixture = Fixture::new("first");
+    fixture.write("a.txt", "a");
+    fixture.write("sub/deep/b.txt", "b");
+    assert_eq!(
+        fixture.sync(),
+        ["mkdir /synced", "upload a.txt", "upload sub/deep/b.txt"]
+    );
+    assert_eq!(
+        fixture.server.library().files(),
+        ["/synced/a.txt", "/synced/sub/deep/b.txt"]
+    );
+    assert_eq!(fixture.read_remote("sub/deep/b.txt"), "b");
+    assert!(fixture.sync().is_empty());
+}
+
+#[test]
+fn first_sync_keeps_identical_files() {
+    let mut fixture = Fixture::new("identical");
+    fixture.write("a.txt", "same");
+    fixture.put_remote("a.txt", "same");
+    assert!(fixture.sync().is_empty());
+    assert!(fixture.state.files.contains_key("a.txt"));
+    assert!(fixture.sync().is_empty());
+}
+
+#[test]
+fn copies_changes_of_one_side() {
+    let mut fixture = Fixture::new("one-side");
+    fixture.write("a.txt", "a");
+    fixture.write("sub/b.txt", "b");
+    fixture.sync();
+    fixture.write("a.txt", "a, changed locally");
+    fixture.write("sub/new.txt", "new locally");
+    fixture.put_remote("sub/b.txt", "b, changed remotely");
+    fixture.put_remote("c.txt", "new remotely");
+    assert_eq!(
+        fixture.sync(),
+        [
+            "upload a.txt",
+            "download c.txt",
+            "download sub/b.txt",
+            "upload sub/new.txt"
+        ]
+    );
+    assert_eq!(fixture.read_remote("a.txt"), "a, changed locally");
+    assert_eq!(fixture.read_remote("sub/new.txt"), "new locally");
+    assert_eq!(fixture.read("sub/b.txt"), "b, changed remotely");
+    assert_eq!(fixture.read("c.txt"), "new remotely");
+    assert!(fixture.sync().is_empty());
+}
+
+#[test]
+fn copies_deletions() {
+    let mut fixture = Fixture::new("deletions");
+    fixture.write("a.txt", "a");
+    fixture.write("b.txt", "b");
+    fixture.write("c.txt", "c");
+    fixture.sync();
+    fs::remove_file(fixture.local.join("a.txt")).unwrap();
+    fixture.server.library().remove("/synced/b.txt");
+    fs::remove_file(fixture.local.join("c.txt")).unwrap();
+    fixture.server.library().remove("/synced/c.txt");
+    assert_eq!(fixture.sync(), ["delete a.txt", "delete-local b.txt"]);
+    assert!(fixture.server.library().files().is_empty());
+    assert!(!fixture.local.join("b.txt").exists());
+    // Deleted on both sides, it is forgotten without a step.
+    assert!(fixture.state.files.is_empty());
+}
+
+#[test]
+fn keeps_both_versions_of_conflicts() {
+    let mut fixture = Fixture::new("conflict");
+    fixture.write("a.txt", "a");
+    fixture.write("notes", "n");
+    fixture.sync();
+    fixture.write("a.txt", "a, changed locally");
+    fixture.put_remote("a.txt", "a, changed remotely");
+    fixture.write("notes", "n, changed locally");
+    fixture.put_remote("notes", "n, changed remotely");
+    // Taken by a file of the user.
+    fixture.write("a (conflicted copy).txt", "mine");
+    assert_eq!(
+        fixture.sync(),
+        [
+            "upload a (conflicted copy).txt",
+            "conflict a.txt as a (conflicted copy 2).txt",
+            "conflict notes as notes (conflicted copy)"
+        ]
+    );
+    assert_eq!(fixture.read("a.txt"), "a, changed remotely");
+    assert_eq!(
+        fixture.read("a (conflicted copy 2).txt"),
+        "a, changed locally"
+    );
+    assert_eq!(
+        fixture.read_remote("a (conflicted copy 2).txt"),
+        "a, changed locally"
+    );
+    assert_eq!(
+        fixture.read("notes (conflicted copy)"),
+        "n, changed locally"
+    );
+    assert!(fixture.sync().is_empty());
+}
+
+#[test]
+fn same_changes_on_both_sides_are_no_conflict() {
+    let mut fixture = Fixture::new("same-change");
+    fixture.write("a.txt", "a");
+    fixture.sync();
+    fixture.write("a.txt", "a, changed the same way");
+    fixture.put_remote("a.txt", "a, changed the same way");
+    assert!(fixture.sync().is_empty());
+    assert!(fixture.sync().is_empty());
+}
+
+#[test]
+fn one_way_uploads_new_and_changed_files() {
+    let fixture = Fixture::new("one-way");
+    fixture.put_remote("kept.txt", "kept");
+    fixture.put_remote("resized.txt", "short");
+    fixture.put_remote("touched.txt", "abc");
+    fixture.put_remote("remote-only.txt", "r");
+    fixture.write("kept.txt", "kept");
+    fixture.touch("kept.txt", 1_500_000_000);
+    fixture.write("resized.txt", "longer");
+    fixture.touch("resized.txt", 1_500_000_000);
+    fixture.write("touched.txt", "xyz");
+    fixture.touch("touched.txt", 1_700_000_000);
+    fixture.write("sub/new.txt", "new");
+    assert_eq!(
+        fixture.sync_one_way(SyncOptions::default()),
+        [
+            "upload resized.txt",
+            "mkdir /synced/sub",
+            "upload sub/new.txt",
+            "upload touched.txt"
+        ]
+    );
+    assert_eq!(fixture.read_remote("resized.txt"), "longer");
+    assert_eq!(fixture.read_remote("touched.txt"), "xyz");
+    assert_eq!(fixture.read_remote("sub/new.txt"), "new");
+    assert_eq!(fixture.read_remote("remote-only.txt"), "r");
+}
+
+#[test]
+fn one_way_deletes_remote_leftovers_only_with_delete() {
+    let fixture = Fixture::new("one-way-delete");
+    fixture.put_remote("gone.txt", "g");
+    fixture.put_remote("old/x.txt", "x");
+    fixture.write("a.txt", "a");
+    assert_eq!(
+        fixture.sync_one_way(SyncOptions::default()),
+        ["upload a.txt"]
+    );
+    assert_eq!(fixture.server.library().files().len(), 3);
+    let options = SyncOptions {
+        delete: true,
+        ..SyncOptions::default()
+    };
+    assert_eq!(
+        fixture.sync_one_way(options),
+        ["upload a.txt", "delete gone.txt", "delete old"]
+    );
+    assert_eq!(fixture.server.library().files(), ["/synced/a.txt"]);
+}
+
+#[test]
+fn one_way_replaces_entries_of_the_other_type_only_with_delete() {
+    let fixture = Fixture::new("one-way-replace");
+    fixture.put_remote("thing/x.txt", "x");
+    fixture.put_remote("other", "o");
+    fixture.write("thing", "now a file");
+    fixture.write("other/y.txt", "y");
+    match fixture
+        .account
+        .plan_sync(&fixture.local, LIBRARY_ID, DIR, &SyncOptions::default())
+    {
+        Err(Error::Conflict(message)) => assert_eq!(
+            message,
+            "/synced/other is a file remotely but not locally, use --delete to replace it"
+        ),
+        other => panic!("expected a conflict, got {:?}", other),
+    }
+    let options = SyncOptions {
+        delete: true,
+        ..SyncOptions::default()
+    };
+    assert_eq!(
+        fixture.sync_one_way(options),
+        [
+            "delete other",
+            "mkdir /synced/other",
+            "upload other/y.txt",
+            "delete thing",
+            "upload thing"
+        ]
+    );
+    assert_eq!(
+        fixture.server.library().files(),
+        ["/synced/other/y.txt", "/synced/thing"]
+    );
+    assert_eq!(fixture.read_remote("thing"), "now a file");
+}
+
+#[test]
+fn one_way_compares_checksums() {
+    let fixture = Fixture::new("one-way-checksum");
+    fixture.put_remote("same.txt", "abc");
+    fixture.put_remote("differs.txt", "abc");
+    fixture.write("same.txt", "abc");
+    fixture.write("differs.txt", "xyz");
+    // Both are newer than the remote files, but only one differs.
+    let options = SyncOptions {
+        compare: Compare::Checksum,
+        ..SyncOptions::default()
+    };
+    assert_eq!(fixture.sync_one_way(options), ["upload differs.txt"]);
+    assert_eq!(fixture.read_remote("differs.txt"), "xyz");
+    assert_eq!(
+        fixture.sync_one_way(SyncOptions::default()),
+        ["upload differs.txt", "upload same.txt"]
+    );
+}
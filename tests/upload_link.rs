@@ -0,0 +1,238 @@
+mod mock;
+
+use mock::{Layout, MockServer, Options, TOKEN};
+use seaf_web::{
+    link,
+    upload::{PageLayout, UploadItem},
+    Error, RetryPolicy, UploadLink,
+};
+use std::{fs, path::PathBuf, sync::Arc};
+
+/// Writes a file to upload into a directory of its own for each test.
+fn local_file(test: &str, name: &str, content: &[u8]) -> PathBuf {
+    let dir = std::env::temp_dir()
+        .join(format!("seaf-web-tests-{}", std::process::id()))
+        .join(test);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    fs::write(&path, content).unwrap();
+    path
+}
+
+fn open(server: &MockServer) -> seaf_web::Result<UploadLink> {
+    UploadLink::open_with_client(link::client()?, RetryPolicy::none(), &server.url, TOKEN)
+}
+
+#[test]
+fn uploads_to_open_link() {
+    let server = MockServer::start(Options::default());
+    let path = local_file("open", "report.txt", b"quarterly numbers");
+    let link = open(&server).unwrap();
+    assert!(!link.needs_password());
+    let resp = link.upload_to(&path, "/reports/2021/").unwrap();
+    assert_eq!(resp.name, "report.txt");
+    assert_eq!(resp.size, 17);
+    let uploads = server.uploads();
+    assert_eq!(uploads.len(), 1);
+    assert_eq!(uploads[0].parent_dir, "/");
+    assert_eq!(uploads[0].relative_path, "reports/2021");
+    assert_eq!(uploads[0].file_name, "report.txt");
+    assert_eq!(uploads[0].content, b"quarterly numbers");
+}
+
+#[test]
+fn uploads_after_password() {
+    let server = MockServer::start(Options {
+        password: Some("secret"),
+        ..Options::default()
+    });
+    let path = local_file("password", "data.bin", &[0, 1, 2, 3]);
+    let mut link = open(&server).unwrap();
+    assert!(link.needs_password());
+    link.authenticate("secret").unwrap();
+    assert!(!link.needs_password());
+    link.upload(&path).unwrap();
+    assert_eq!(server.uploads()[0].content, [0, 1, 2, 3]);
+}
+
+#[test]
+fn uploads_in_chunks() {
+    let server = MockServer::start(Options::default());
+    let content: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+    let path = local_file("chunks", "big.bin", &content);
+    let link = open(&server).unwrap();
+    let item = UploadItem {
+        path,
+        relative_path: String::new(),
+    };
+    let options = seaf_web::upload::UploadOptions {
+        chunk_size: 4096,
+        ..Default::default()
+    };
+    let progress: Arc<dyn seaf_web::Progress> = Arc::new(seaf_web::progress::NoProgress);
+    link.upload_item(&item, &options, &progress).unwrap();
+    let uploads = server.uploads();
+    assert_eq!(uploads.len(), 3);
+    let received: Vec<u8> = uploads
+        .into_iter()
+        .flat_map(|upload| upload.content)
+        .collect();
+    assert_eq!(received, content);
+}
+
+#[test]
+fn rejects_wrong_password() {
+    let server = MockServer::start(Options {
+        password: Some("secret"),
+        ..Options::default()
+    });
+    let mut link = open(&server).unwrap();
+    assert!(matches!(
+        link.authenticate("guess"),
+        Err(Error::WrongPassword)
+    ));
+    assert!(link.needs_password());
+    assert!(server.uploads().is_empty());
+}
+
+#[test]
+fn fails_without_form_or_script() {
+    let server = MockServer::start(Options {
+        layout: Layout::Unknown,
+        ..Options::default()
+    });
+    assert!(matches!(open(&server), Err(Error::Layout(_))));
+}
+
+#[test]
+fn reports_failed_upload() {
+    let server = MockServer::start(Options {
+        fail_uploads: true,
+        ..Options::default()
+    });
+    let path = local_file("failed", "report.txt", b"lost");
+    let link = open(&server).unwrap();
+    match link.upload(&path) {
+        Err(Error::Rejected(msg)) => assert!(msg.starts_with("500"), "{}", msg),
+        other => panic!(
+            "expected a rejected upload, got {:?}",
+            other.map(|resp| resp.name)
+        ),
+    }
+}
+
+#[test]
+fn uploads_through_react_page() {
+    let server = MockServer::start(Options {
+        version: Some("11.0.12"),
+        layout: Layout::React,
+        ..Options::default()
+    });
+    let path = local_file("react", "notes.md", b"# Notes");
+    let link = open(&server).unwrap();
+    assert_eq!(link.layout(), Some(PageLayout::React));
+    let resp = link.upload_to(&path, "drafts").unwrap();
+    assert_eq!(resp.name, "notes.md");
+    let uploads = server.uploads();
+    assert_eq!(uploads[0].relative_path, "drafts");
+    assert_eq!(uploads[0].content, b"# Notes");
+}
+
+#[test]
+fn uploads_through_react_page_after_password() {
+    let server = MockServer::start(Options {
+        password: Some("secret"),
+        version: Some("9.0.16"),
+        layout: Layout::React,
+        ..Options::default()
+    });
+    let path = local_file("react-password", "data.bin", &[4, 5, 6]);
+    let mut link = open(&server).unwrap();
+    assert!(link.needs_password());
+    assert_eq!(link.layout(), Some(PageLayout::React));
+    link.authenticate("secret").unwrap();
+    link.upload(&path).unwrap();
+    assert_eq!(server.uploads()[0].content, [4, 5, 6]);
+}
+
+#[test]
+fn uses_script_of_old_versions() {
+    let server = MockServer::start(Options {
+        version: Some("8.0.8"),
+        ..Options::default()
+    });
+    let path = local_file("old", "report.txt", b"old");
+    let link = open(&server).unwrap();
+    assert_eq!(link.layout(), Some(PageLayout::Script));
+    link.upload(&path).unwrap();
+    assert_eq!(server.uploads().len(), 1);
+}
+
+#[test]
+fn detects_layout_without_server_info() {
+    let server = MockServer::start(Options {
+        password: Some("secret"),
+        layout: Layout::React,
+        ..Options::default()
+    });
+    let path = local_file("no-version", "report.txt", b"new");
+    let mut link = open(&server).unwrap();
+    assert_eq!(link.layout(), None);
+    link.authenticate("secret").unwrap();
+    assert_eq!(link.layout(), Some(PageLayout::React));
+    link.upload(&path).unwrap();
+    assert_eq!(server.uploads().len(), 1);
+}
+
+#[test]
+fn fails_on_page_of_other_version() {
+    let server = MockServer::start(Options {
+        version: Some("10.0.1"),
+        ..Options::default()
+    });
+    match open(&server) {
+        Err(Error::Layout(msg)) => assert_eq!(msg, "no page options found"),
+        other => panic!("expected a layout error, got {:?}", other.err()),
+    }
+}
+
+#[test]
+fn uploads_streams_through_react_page() {
+    let server = MockServer::start(Options {
+        version: Some("10.0.1"),
+        layout: Layout::React,
+        ..Options::default()
+    });
+    let link = open(&server).unwrap();
+    let options = seaf_web::upload::UploadOptions {
+        chunk_size: 4096,
+        ..Default::default()
+    };
+    let progress: Arc<dyn seaf_web::Progress> = Arc::new(seaf_web::progress::NoProgress);
+    let short = b"one line\n".to_vec();
+    let long: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+    for (name, content) in [("short.log", &short), ("long.log", &long)] {
+        let resp = link
+            .upload_stream(
+                std::io::Cursor::new(content.clone()),
+                name,
+                "logs",
+                &options,
+                &progress,
+            )
+            .unwrap();
+        assert_eq!(resp.name, name);
+    }
+    // The long stream goes in three chunks.
+    let uploads = server.uploads();
+    assert_eq!(uploads.len(), 4);
+    assert_eq!(uploads[0].content, short);
+    assert!(uploads[1..]
+        .iter()
+        .all(|upload| upload.relative_path == "logs" && upload.file_name == "long.log"));
+    let received: Vec<u8> = uploads[1..]
+        .iter()
+        .flat_map(|upload| upload.content.clone())
+        .collect();
+    assert_eq!(received, long);
+}